@@ -1,18 +1,31 @@
 use z3_sys::*;
+use ffi::{self, Z3_is_eq_ast, Z3_get_numeral_small,
+          Z3_get_numeral_int64, Z3_get_numeral_uint64,
+          Z3_mk_goal, Z3_goal_is_decided_unsat,
+          Z3_mk_atleast, Z3_mk_pble, Z3_mk_pbge, Z3_mk_pbeq,
+          Z3_mk_lambda, Z3_mk_lambda_const, Z3_mk_select_n, Z3_mk_store_n,
+          Z3_mk_set_has_size};
 use Context;
 use Sort;
 use Symbol;
 use Ast;
-use Z3_MUTEX;
+use Params;
+use FuncDecl;
+use Capability;
+use version;
+use std::collections::HashMap;
+use std::ptr;
 use std::hash::{Hash, Hasher};
 use std::cmp::{PartialEq, Eq};
 use std::ffi::CString;
+use z3_string;
+use z3_len;
+use std::fmt;
 
 macro_rules! unop {
     ( $f:ident, $z3fn:ident ) => {
         pub fn $f(&self) -> Ast<'ctx> {
             Ast::new(self.ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 $z3fn(self.ctx.z3_ctx, self.z3_ast)
             })
     }
@@ -23,7 +36,6 @@ macro_rules! binop {
     ( $f:ident, $z3fn:ident ) => {
         pub fn $f(&self, other: &Ast<'ctx>) -> Ast<'ctx> {
             Ast::new(self.ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 $z3fn(self.ctx.z3_ctx, self.z3_ast, other.z3_ast)
             })
     }
@@ -34,7 +46,6 @@ macro_rules! trinop {
     ( $f:ident, $z3fn:ident ) => {
         pub fn $f(&self, a: &Ast<'ctx>, b: &Ast<'ctx>) -> Ast<'ctx> {
             Ast::new(self.ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 $z3fn(self.ctx.z3_ctx, self.z3_ast, a.z3_ast, b.z3_ast)
             })
     }
@@ -45,13 +56,11 @@ macro_rules! varop {
     ( $f:ident, $z3fn:ident ) => {
         pub fn $f(&self, other: &[&Ast<'ctx>]) -> Ast<'ctx> {
             Ast::new(self.ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 let mut tmp = vec![self.z3_ast];
                 for a in other {
                     tmp.push(a.z3_ast)
                 }
-                assert!(tmp.len() <= 0xffffffff);
-                $z3fn(self.ctx.z3_ctx, tmp.len() as u32, tmp.as_ptr())
+                $z3fn(self.ctx.z3_ctx, z3_len(&tmp), tmp.as_ptr())
             })
     }
     };
@@ -59,23 +68,97 @@ macro_rules! varop {
 
 impl<'ctx> Ast<'ctx> {
 
-    pub fn new(ctx: &Context, ast: Z3_ast) -> Ast {
+    // Takes a reference on a raw term of this context.
+    pub(crate) fn new(ctx: &'ctx Context, ast: Z3_ast) -> Ast<'ctx> {
         assert!(!ast.is_null());
         Ast {
-            ctx: ctx,
+            ctx,
             z3_ast: unsafe {
                 debug!("new ast {:p}", ast);
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_inc_ref(ctx.z3_ctx, ast);
                 ast
             }
         }
     }
 
+    // Copies out the elements of a vector Z3 handed back to us; the vector
+    // itself is released before returning.
+    pub(crate) fn from_z3_ast_vector(ctx: &'ctx Context,
+                                     v: Z3_ast_vector) -> Vec<Ast<'ctx>> {
+        let raw : Vec<Z3_ast> = unsafe {
+            Z3_ast_vector_inc_ref(ctx.z3_ctx, v);
+            let n = Z3_ast_vector_size(ctx.z3_ctx, v);
+            (0..n).map(|i| Z3_ast_vector_get(ctx.z3_ctx, v, i)).collect()
+        };
+        let asts = raw.into_iter().map(|a| Ast::new(ctx, a)).collect();
+        unsafe {
+            Z3_ast_vector_dec_ref(ctx.z3_ctx, v);
+        }
+        asts
+    }
+
+    // A fresh, referenced Z3 vector holding the given terms; the caller
+    // releases it with Z3_ast_vector_dec_ref.
+    pub(crate) fn to_z3_ast_vector(ctx: &'ctx Context,
+                                   asts: &[&Ast<'ctx>]) -> Z3_ast_vector {
+        unsafe {
+            let v = Z3_mk_ast_vector(ctx.z3_ctx);
+            Z3_ast_vector_inc_ref(ctx.z3_ctx, v);
+            for a in asts {
+                Z3_ast_vector_push(ctx.z3_ctx, v, a.z3_ast);
+            }
+            v
+        }
+    }
+
+    // The name of the function this term applies, such as "=" or "and",
+    // and its arguments; None for anything but applications.
+    pub(crate) fn app_name(&self) -> Option<String> {
+        unsafe {
+            if Z3_get_ast_kind(self.ctx.z3_ctx, self.z3_ast) != Z3_APP_AST {
+                return None;
+            }
+            let app = Z3_to_app(self.ctx.z3_ctx, self.z3_ast);
+            let sym = Z3_get_decl_name(self.ctx.z3_ctx,
+                                       Z3_get_app_decl(self.ctx.z3_ctx, app));
+            let p = Z3_get_symbol_string(self.ctx.z3_ctx, sym);
+            Some(z3_string(p))
+        }
+    }
+
+    pub fn get_sort(&self) -> Sort<'ctx> {
+        Sort::from_z3(self.ctx, unsafe {
+            Z3_get_sort(self.ctx.z3_ctx, self.z3_ast)
+        })
+    }
+
+    // The function this term applies, built-in ones such as + included;
+    // with array_map, that lifts operators to arrays.
+    pub fn decl(&self) -> Option<FuncDecl<'ctx>> {
+        unsafe {
+            if Z3_get_ast_kind(self.ctx.z3_ctx, self.z3_ast) != Z3_APP_AST {
+                return None;
+            }
+            let app = Z3_to_app(self.ctx.z3_ctx, self.z3_ast);
+            Some(FuncDecl::from_z3(self.ctx, Z3_get_app_decl(self.ctx.z3_ctx, app)))
+        }
+    }
+
+    pub(crate) fn app_args(&self) -> Vec<Ast<'ctx>> {
+        unsafe {
+            if Z3_get_ast_kind(self.ctx.z3_ctx, self.z3_ast) != Z3_APP_AST {
+                return Vec::new();
+            }
+            let app = Z3_to_app(self.ctx.z3_ctx, self.z3_ast);
+            (0..Z3_get_app_num_args(self.ctx.z3_ctx, app))
+                .map(|i| Ast::new(self.ctx, Z3_get_app_arg(self.ctx.z3_ctx, app, i)))
+                .collect()
+        }
+    }
+
     pub fn new_const(sym: &Symbol<'ctx>,
                      sort: &Sort<'ctx>) -> Ast<'ctx> {
         Ast::new(sym.ctx, unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_mk_const(sym.ctx.z3_ctx, sym.z3_sym, sort.z3_sort)
         })
     }
@@ -86,14 +169,12 @@ impl<'ctx> Ast<'ctx> {
         Ast::new(ctx, unsafe {
             let pp = CString::new(prefix).unwrap();
             let p = pp.as_ptr();
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_mk_fresh_const(ctx.z3_ctx, p, sort.z3_sort)
         })
     }
 
     pub fn from_bool(ctx: &'ctx Context, b: bool) -> Ast<'ctx> {
             Ast::new(ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 if b {
                     Z3_mk_true(ctx.z3_ctx)
                 } else {
@@ -105,7 +186,6 @@ impl<'ctx> Ast<'ctx> {
     pub fn from_i64(ctx: &'ctx Context, i: i64) -> Ast<'ctx> {
             Ast::new(ctx, unsafe {
                 let sort = ctx.int_sort();
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_mk_int64(ctx.z3_ctx, i, sort.z3_sort)
             })
     }
@@ -113,23 +193,297 @@ impl<'ctx> Ast<'ctx> {
     pub fn from_u64(ctx: &'ctx Context, u: u64) -> Ast<'ctx> {
             Ast::new(ctx, unsafe {
                 let sort = ctx.int_sort();
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_mk_unsigned_int64(ctx.z3_ctx, u, sort.z3_sort)
             })
     }
 
+    // A numeral of any numeric sort, finite domains included.
+    pub fn from_u64_sorted(sort: &Sort<'ctx>, u: u64) -> Ast<'ctx> {
+        Ast::new(sort.ctx, unsafe {
+            Z3_mk_unsigned_int64(sort.ctx.z3_ctx, u, sort.z3_sort)
+        })
+    }
+
+    pub fn forall_const(ctx: &'ctx Context, bound: &[&Ast<'ctx>],
+                        body: &Ast<'ctx>) -> Ast<'ctx> {
+        let tmp : Vec<Z3_app> = bound.iter().map(|b| unsafe {
+            Z3_to_app(ctx.z3_ctx, b.z3_ast)
+        }).collect();
+        Ast::new(ctx, unsafe {
+            Z3_mk_forall_const(ctx.z3_ctx, 0, z3_len(&tmp),
+                               tmp.as_ptr(), 0, ptr::null(), body.z3_ast)
+        })
+    }
+
+    pub fn exists_const(ctx: &'ctx Context, bound: &[&Ast<'ctx>],
+                        body: &Ast<'ctx>) -> Ast<'ctx> {
+        let tmp : Vec<Z3_app> = bound.iter().map(|b| unsafe {
+            Z3_to_app(ctx.z3_ctx, b.z3_ast)
+        }).collect();
+        Ast::new(ctx, unsafe {
+            Z3_mk_exists_const(ctx.z3_ctx, 0, z3_len(&tmp),
+                               tmp.as_ptr(), 0, ptr::null(), body.z3_ast)
+        })
+    }
+
+    // The array mapping every index of the domain to value.
+    pub fn const_array(domain: &Sort<'ctx>, value: &Ast<'ctx>) -> Ast<'ctx> {
+        Ast::new(domain.ctx, unsafe {
+            Z3_mk_const_array(domain.ctx.z3_ctx, domain.z3_sort, value.z3_ast)
+        })
+    }
+
+    // The array taking indices to body, with the indices as bound
+    // variables; as with quantifiers, the last one is bound variable 0.
+    pub fn lambda(ctx: &'ctx Context, bound: &[(&Symbol<'ctx>, &Sort<'ctx>)],
+                  body: &Ast<'ctx>) -> Result<Ast<'ctx>, String> {
+        version().require(Capability::Lambda)?;
+        let names : Vec<Z3_symbol> = bound.iter().map(|&(s, _)| s.z3_sym).collect();
+        let sorts : Vec<Z3_sort> = bound.iter().map(|&(_, s)| s.z3_sort).collect();
+        Ok(Ast::new(ctx, unsafe {
+            Z3_mk_lambda(ctx.z3_ctx, z3_len(&names),
+                         sorts.as_ptr(), names.as_ptr(), body.z3_ast)
+        }))
+    }
+
+    // The array taking indices to body, with the given constants
+    // standing for the indices.
+    pub fn lambda_const(ctx: &'ctx Context, bound: &[&Ast<'ctx>],
+                        body: &Ast<'ctx>) -> Result<Ast<'ctx>, String> {
+        version().require(Capability::Lambda)?;
+        let tmp : Vec<Z3_app> = bound.iter().map(|b| unsafe {
+            Z3_to_app(ctx.z3_ctx, b.z3_ast)
+        }).collect();
+        Ok(Ast::new(ctx, unsafe {
+            Z3_mk_lambda_const(ctx.z3_ctx, z3_len(&tmp),
+                               tmp.as_ptr(), body.z3_ast)
+        }))
+    }
+
+    // Applies f pointwise: the result holds f(a1[i], .., an[i]) at i.
+    pub fn array_map(f: &FuncDecl<'ctx>, arrays: &[&Ast<'ctx>]) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = arrays.iter().map(|a| a.z3_ast).collect();
+        Ast::new(f.ctx, unsafe {
+            Z3_mk_map(f.ctx.z3_ctx, f.z3_func_decl,
+                      z3_len(&tmp), tmp.as_ptr())
+        })
+    }
+
+    pub fn empty_set(domain: &Sort<'ctx>) -> Ast<'ctx> {
+        Ast::new(domain.ctx, unsafe {
+            Z3_mk_empty_set(domain.ctx.z3_ctx, domain.z3_sort)
+        })
+    }
+
+    pub fn full_set(domain: &Sort<'ctx>) -> Ast<'ctx> {
+        Ast::new(domain.ctx, unsafe {
+            Z3_mk_full_set(domain.ctx.z3_ctx, domain.z3_sort)
+        })
+    }
+
+    // Cardinality constraints: at most (or at least) k of the given
+    // booleans hold.
+    pub fn at_most(ctx: &'ctx Context, args: &[&Ast<'ctx>], k: u32) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = args.iter().map(|a| a.z3_ast).collect();
+        Ast::new(ctx, unsafe {
+            Z3_mk_atmost(ctx.z3_ctx, z3_len(&tmp),
+                         tmp.as_ptr(), k)
+        })
+    }
+
+    pub fn at_least(ctx: &'ctx Context, args: &[&Ast<'ctx>], k: u32) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = args.iter().map(|a| a.z3_ast).collect();
+        Ast::new(ctx, unsafe {
+            Z3_mk_atleast(ctx.z3_ctx, z3_len(&tmp),
+                          tmp.as_ptr(), k)
+        })
+    }
+
+    // Pseudo-Boolean constraints: the coefficients of the booleans that
+    // hold sum to at most, at least or exactly k.
+    pub fn pb_le(ctx: &'ctx Context, args: &[(&Ast<'ctx>, i32)], k: i32) -> Ast<'ctx> {
+        Ast::pb(ctx, Z3_mk_pble, args, k)
+    }
+
+    pub fn pb_ge(ctx: &'ctx Context, args: &[(&Ast<'ctx>, i32)], k: i32) -> Ast<'ctx> {
+        Ast::pb(ctx, Z3_mk_pbge, args, k)
+    }
+
+    pub fn pb_eq(ctx: &'ctx Context, args: &[(&Ast<'ctx>, i32)], k: i32) -> Ast<'ctx> {
+        Ast::pb(ctx, Z3_mk_pbeq, args, k)
+    }
+
+    fn pb(ctx: &'ctx Context,
+          mk: unsafe extern "C" fn(Z3_context, ::libc::c_uint, *const Z3_ast,
+                                   *const ::libc::c_int, ::libc::c_int) -> Z3_ast,
+          args: &[(&Ast<'ctx>, i32)], k: i32) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = args.iter().map(|&(a, _)| a.z3_ast).collect();
+        let coeffs : Vec<::libc::c_int> = args.iter().map(|&(_, c)| c).collect();
+        Ast::new(ctx, unsafe {
+            mk(ctx.z3_ctx, z3_len(&tmp), tmp.as_ptr(),
+               coeffs.as_ptr(), k)
+        })
+    }
+
     pub fn from_real(ctx: &'ctx Context, num: i32, den: i32) -> Ast<'ctx> {
             Ast::new(ctx, unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_mk_real(ctx.z3_ctx,
                            num as ::libc::c_int,
                            den as ::libc::c_int)
             })
     }
 
+    // The variable with de Bruijn index idx, for bodies handed to
+    // substitute_vars and substitute_funs.
+    pub fn bound_var(ctx: &'ctx Context, idx: u32, sort: &Sort<'ctx>) -> Ast<'ctx> {
+        Ast::new(ctx, unsafe {
+            Z3_mk_bound(ctx.z3_ctx, idx as ::libc::c_uint, sort.z3_sort)
+        })
+    }
+
+    // Replaces every occurrence of each `from` term by its `to` term, all
+    // at once.
+    pub fn substitute(&self, subst: &[(&Ast<'ctx>, &Ast<'ctx>)]) -> Ast<'ctx> {
+        let from : Vec<Z3_ast> = subst.iter().map(|&(f, _)| f.z3_ast).collect();
+        let to : Vec<Z3_ast> = subst.iter().map(|&(_, t)| t.z3_ast).collect();
+        Ast::new(self.ctx, unsafe {
+            Z3_substitute(self.ctx.z3_ctx, self.z3_ast,
+                          z3_len(&from),
+                          from.as_ptr(), to.as_ptr())
+        })
+    }
+
+    // Replaces the free variable with de Bruijn index i by to[i].
+    pub fn substitute_vars(&self, to: &[&Ast<'ctx>]) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = to.iter().map(|a| a.z3_ast).collect();
+        Ast::new(self.ctx, unsafe {
+            Z3_substitute_vars(self.ctx.z3_ctx, self.z3_ast,
+                               z3_len(&tmp), tmp.as_ptr())
+        })
+    }
+
+    // Inlines function definitions: every application f(a_0, ..., a_n) of
+    // a listed f becomes its body with variable i replaced by a_i. Z3 only
+    // gained Z3_substitute_funs in 4.12, so the term is rewritten here.
+    pub fn substitute_funs(&self, subst: &[(&FuncDecl<'ctx>, &Ast<'ctx>)]) -> Ast<'ctx> {
+        let mut done = HashMap::new();
+        self.substitute_funs_rec(subst, &mut done)
+    }
+
+    fn substitute_funs_rec(&self, subst: &[(&FuncDecl<'ctx>, &Ast<'ctx>)],
+                           done: &mut HashMap<usize, Ast<'ctx>>) -> Ast<'ctx> {
+        let key = self.z3_ast as usize;
+        if let Some(a) = done.get(&key) {
+            return a.clone();
+        }
+        let c = self.ctx.z3_ctx;
+        let res = unsafe {
+            match Z3_get_ast_kind(c, self.z3_ast) {
+                Z3_APP_AST => {
+                    let app = Z3_to_app(c, self.z3_ast);
+                    let args : Vec<Ast<'ctx>> = (0..Z3_get_app_num_args(c, app))
+                        .map(|i| Ast::new(self.ctx, Z3_get_app_arg(c, app, i))
+                             .substitute_funs_rec(subst, done))
+                        .collect();
+                    let decl = Z3_func_decl_to_ast(c, Z3_get_app_decl(c, app));
+                    let def = subst.iter().find(|&&(f, _)| {
+                        Z3_is_eq_ast(c, Z3_func_decl_to_ast(c, f.z3_func_decl), decl)
+                    });
+                    match def {
+                        Some(&(_, body)) => {
+                            let body = body.substitute_funs_rec(subst, done);
+                            body.substitute_vars(&args.iter().collect::<Vec<&Ast>>())
+                        }
+                        None => self.update(&args)
+                    }
+                }
+                Z3_QUANTIFIER_AST => {
+                    let body = Ast::new(self.ctx, Z3_get_quantifier_body(c, self.z3_ast));
+                    self.update(&[body.substitute_funs_rec(subst, done)])
+                }
+                _ => self.clone()
+            }
+        };
+        done.insert(key, res.clone());
+        res
+    }
+
+    // This term with its arguments (or quantifier body) swapped out.
+    pub(crate) fn update(&self, args: &[Ast<'ctx>]) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = args.iter().map(|a| a.z3_ast).collect();
+        Ast::new(self.ctx, unsafe {
+            Z3_update_term(self.ctx.z3_ctx, self.z3_ast,
+                           z3_len(&tmp), tmp.as_ptr())
+        })
+    }
+
+    // The same term, rebuilt in another context.
+    pub fn translate<'dest>(&self, dest: &'dest Context) -> Ast<'dest> {
+        Ast::new(dest, unsafe {
+            Z3_translate(self.ctx.z3_ctx, self.z3_ast, dest.z3_ctx)
+        })
+    }
+
+    pub fn simplify(&self) -> Ast<'ctx> {
+        Ast::new(self.ctx, unsafe {
+            Z3_simplify(self.ctx.z3_ctx, self.z3_ast)
+        })
+    }
+
+    // See Context::simplify_help for the parameters understood.
+    pub fn simplify_with(&self, params: &Params<'ctx>) -> Ast<'ctx> {
+        Ast::new(self.ctx, unsafe {
+            Z3_simplify_ex(self.ctx.z3_ctx, self.z3_ast, params.z3_params)
+        })
+    }
+
+    // Much slower than simplify, but drops whatever parts of a boolean
+    // formula the rest of it already implies, by asking a solver.
+    pub fn ctx_solver_simplify(&self) -> Ast<'ctx> {
+        self.apply_tactic("ctx-solver-simplify")
+    }
+
+    // Runs a tactic on this formula as a goal and returns what is left,
+    // as the disjunction of the resulting subgoals.
+    fn apply_tactic(&self, name: &str) -> Ast<'ctx> {
+        let ctx = self.ctx;
+        let tn = CString::new(name).unwrap();
+        let mut subgoals = Vec::new();
+        unsafe {
+            let t = Z3_mk_tactic(ctx.z3_ctx, tn.as_ptr());
+            Z3_tactic_inc_ref(ctx.z3_ctx, t);
+            let g = Z3_mk_goal(ctx.z3_ctx, false, false, false);
+            Z3_goal_inc_ref(ctx.z3_ctx, g);
+            Z3_goal_assert(ctx.z3_ctx, g, self.z3_ast);
+            let r = Z3_tactic_apply(ctx.z3_ctx, t, g);
+            Z3_apply_result_inc_ref(ctx.z3_ctx, r);
+            for i in 0..Z3_apply_result_get_num_subgoals(ctx.z3_ctx, r) {
+                let sg = Z3_apply_result_get_subgoal(ctx.z3_ctx, r, i);
+                if Z3_goal_is_decided_unsat(ctx.z3_ctx, sg) {
+                    continue;
+                }
+                let fs: Vec<Ast<'ctx>> = (0..Z3_goal_size(ctx.z3_ctx, sg))
+                    .map(|j| Ast::new(ctx, Z3_goal_formula(ctx.z3_ctx, sg, j)))
+                    .collect();
+                subgoals.push(match fs.len() {
+                    0 => ctx.from_bool(true),
+                    1 => fs.into_iter().next().unwrap(),
+                    _ => fs[0].and(&fs[1..].iter().collect::<Vec<&Ast>>())
+                });
+            }
+            Z3_apply_result_dec_ref(ctx.z3_ctx, r);
+            Z3_goal_dec_ref(ctx.z3_ctx, g);
+            Z3_tactic_dec_ref(ctx.z3_ctx, t);
+        }
+        match subgoals.len() {
+            0 => ctx.from_bool(false),
+            1 => subgoals.into_iter().next().unwrap(),
+            _ => subgoals[0].or(&subgoals[1..].iter().collect::<Vec<&Ast>>())
+        }
+    }
+
     pub fn as_bool(&self) -> Option<bool> {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             match Z3_get_bool_value(self.ctx.z3_ctx, self.z3_ast) {
                 Z3_L_TRUE => Some(true),
                 Z3_L_FALSE => Some(false),
@@ -140,10 +494,9 @@ impl<'ctx> Ast<'ctx> {
 
     pub fn as_i64(&self) -> Option<i64> {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            let mut tmp : ::libc::c_longlong = 0;
-            if Z3_TRUE == Z3_get_numeral_int64(self.ctx.z3_ctx,
-                                               self.z3_ast, &mut tmp) {
+            let mut tmp : i64 = 0;
+            if Z3_get_numeral_int64(self.ctx.z3_ctx,
+                                    self.z3_ast, &mut tmp) {
                 Some(tmp)
             } else {
                 None
@@ -153,10 +506,9 @@ impl<'ctx> Ast<'ctx> {
 
     pub fn as_u64(&self) -> Option<u64> {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            let mut tmp : ::libc::c_ulonglong = 0;
-            if Z3_TRUE == Z3_get_numeral_uint64(self.ctx.z3_ctx,
-                                                self.z3_ast, &mut tmp) {
+            let mut tmp : u64 = 0;
+            if Z3_get_numeral_uint64(self.ctx.z3_ctx,
+                                     self.z3_ast, &mut tmp) {
                 Some(tmp)
             } else {
                 None
@@ -166,12 +518,11 @@ impl<'ctx> Ast<'ctx> {
 
     pub fn as_real(&self) -> Option<(i64,i64)> {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             let mut num : i64 = 0;
             let mut den : i64 = 0;
-            if Z3_TRUE == Z3_get_numeral_small(self.ctx.z3_ctx,
-                                               self.z3_ast,
-                                               &mut num, &mut den) {
+            if Z3_get_numeral_small(self.ctx.z3_ctx,
+                                    self.z3_ast,
+                                    &mut num, &mut den) {
                 Some((num,den))
             } else {
                 None
@@ -243,6 +594,29 @@ impl<'ctx> Ast<'ctx> {
     // Array ops
     binop!(select, Z3_mk_select);
     trinop!(store, Z3_mk_store);
+    // The value at all but finitely many indices, when there is one.
+    unop!(array_default, Z3_mk_array_default);
+    // An index at which the two arrays differ, if they differ at all.
+    binop!(array_ext, Z3_mk_array_ext);
+
+    pub fn select_n(&self, idxs: &[&Ast<'ctx>]) -> Result<Ast<'ctx>, String> {
+        version().require(Capability::ArrayN)?;
+        let tmp : Vec<Z3_ast> = idxs.iter().map(|a| a.z3_ast).collect();
+        Ok(Ast::new(self.ctx, unsafe {
+            Z3_mk_select_n(self.ctx.z3_ctx, self.z3_ast,
+                           z3_len(&tmp), tmp.as_ptr())
+        }))
+    }
+
+    pub fn store_n(&self, idxs: &[&Ast<'ctx>],
+                   v: &Ast<'ctx>) -> Result<Ast<'ctx>, String> {
+        version().require(Capability::ArrayN)?;
+        let tmp : Vec<Z3_ast> = idxs.iter().map(|a| a.z3_ast).collect();
+        Ok(Ast::new(self.ctx, unsafe {
+            Z3_mk_store_n(self.ctx.z3_ctx, self.z3_ast,
+                          z3_len(&tmp), tmp.as_ptr(), v.z3_ast)
+        }))
+    }
 
     // Set ops
     binop!(set_add, Z3_mk_set_add);
@@ -252,13 +626,61 @@ impl<'ctx> Ast<'ctx> {
     binop!(set_member, Z3_mk_set_member);
     binop!(set_subset, Z3_mk_set_subset);
     unop!(set_complement, Z3_mk_set_complement);
+    binop!(set_difference, Z3_mk_set_difference);
+
+    // The set is finite, with the given (integer) number of members.
+    // None without Capability::SetHasSize, or if Z3 rejects it anyway;
+    // count the members with set_size_within instead.
+    pub fn set_has_size(&self, k: &Ast<'ctx>) -> Option<Ast<'ctx>> {
+        if !version().supports(Capability::SetHasSize) ||
+            !ffi::linked("Z3_mk_set_has_size") {
+            return None;
+        }
+        let (a, err) = self.ctx.catching(|| unsafe {
+            Z3_mk_set_has_size(self.ctx.z3_ctx, self.z3_ast, k.z3_ast)
+        });
+        if err.is_some() || a.is_null() {
+            None
+        } else {
+            Some(Ast::new(self.ctx, a))
+        }
+    }
+
+    // How many of the given, pairwise distinct, elements the set has;
+    // its cardinality when they cover the domain.
+    pub fn set_size_within(&self, elts: &[&Ast<'ctx>]) -> Ast<'ctx> {
+        let zero = Ast::from_i64(self.ctx, 0);
+        let one = Ast::from_i64(self.ctx, 1);
+        let counts : Vec<Ast<'ctx>> = elts.iter()
+            .map(|e| e.set_member(self).ite(&one, &zero))
+            .collect();
+        match counts.split_first() {
+            Some((first, rest)) => first.add(&rest.iter().collect::<Vec<_>>()),
+            None => zero
+        }
+    }
+}
+
+impl<'ctx> fmt::Display for Ast<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = unsafe {
+            let p = Z3_ast_to_string(self.ctx.z3_ctx, self.z3_ast);
+            z3_string(p)
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl<'ctx> Clone for Ast<'ctx> {
+    fn clone(&self) -> Ast<'ctx> {
+        Ast::new(self.ctx, self.z3_ast)
+    }
 }
 
 impl<'ctx> Drop for Ast<'ctx> {
     fn drop(&mut self) {
         unsafe {
             debug!("drop ast {:p}", self.z3_ast);
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_dec_ref(self.ctx.z3_ctx, self.z3_ast);
         }
     }
@@ -276,9 +698,9 @@ impl<'ctx> Hash for Ast<'ctx> {
 impl<'ctx> PartialEq<Ast<'ctx>> for Ast<'ctx> {
     fn eq(&self, other: &Ast<'ctx>) -> bool {
         unsafe {
-            Z3_TRUE == Z3_is_eq_ast(self.ctx.z3_ctx,
-                                    self.z3_ast,
-                                    other.z3_ast)
+            Z3_is_eq_ast(self.ctx.z3_ctx,
+                         self.z3_ast,
+                         other.z3_ast)
         }
     }
 }
@@ -0,0 +1,226 @@
+use z3_sys::*;
+use Context;
+use Chc;
+use ChcResult;
+use Fixedpoint;
+use FuncDecl;
+use Params;
+use Symbol;
+use Sort;
+use SortKind;
+use Ast;
+use SatResult;
+use ReasonUnknown;
+use Capability;
+use version;
+use ffi::Z3_is_quantifier_forall;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+impl<'ctx> Chc<'ctx> {
+    pub fn new(ctx: &'ctx Context) -> Result<Chc<'ctx>, String> {
+        version().require(Capability::Spacer)?;
+        let fp = Fixedpoint::new(ctx);
+        let mut params = Params::new(ctx);
+        params.set_symbol("engine", "spacer");
+        // Keep every predicate through preprocessing, so that each ends
+        // up with an invariant of its own.
+        params.set_bool("xform.slice", false);
+        params.set_bool("xform.inline_linear", false);
+        params.set_bool("xform.inline_eager", false);
+        fp.set_params(&params);
+        let error = FuncDecl::new(&Symbol::from_string(ctx, "chc!error"),
+                                  &[], &ctx.bool_sort());
+        fp.register_relation(&error);
+        Ok(Chc {
+            fp,
+            predicates: RefCell::new(Vec::new()),
+            error
+        })
+    }
+
+    // A problem in the SMT-LIB2 HORN logic: every uninterpreted boolean
+    // function becomes a predicate, every assertion a rule, and the
+    // clauses whose head is false make up what check() looks for.
+    pub fn from_string(ctx: &'ctx Context, smt2: &str) -> Result<Chc<'ctx>, String> {
+        let chc = Chc::new(ctx)?;
+        let clauses = chc.fp.from_string(smt2)?;
+        chc.add_clauses(&clauses);
+        Ok(chc)
+    }
+
+    pub fn from_file(ctx: &'ctx Context, path: &str) -> Result<Chc<'ctx>, String> {
+        let chc = Chc::new(ctx)?;
+        let clauses = chc.fp.from_file(path)?;
+        chc.add_clauses(&clauses);
+        Ok(chc)
+    }
+
+    fn add_clauses(&self, clauses: &[Ast<'ctx>]) {
+        let mut seen = HashSet::new();
+        for c in clauses {
+            collect_predicates(c, &mut seen, &mut |f| self.add_predicate(&f));
+        }
+        for c in clauses {
+            self.rule(c);
+        }
+    }
+
+    pub fn predicate(&self, name: &str, domain: &[&Sort<'ctx>]) -> FuncDecl<'ctx> {
+        let ctx = self.fp.ctx;
+        let f = FuncDecl::new(&Symbol::from_string(ctx, name), domain,
+                              &ctx.bool_sort());
+        self.add_predicate(&f);
+        f
+    }
+
+    pub fn add_predicate(&self, f: &FuncDecl<'ctx>) {
+        if !self.predicates.borrow().contains(f) {
+            self.fp.register_relation(f);
+            self.predicates.borrow_mut().push(f.clone());
+        }
+    }
+
+    pub fn predicates(&self) -> Vec<FuncDecl<'ctx>> {
+        self.predicates.borrow().clone()
+    }
+
+    // A clause such as `body.implies(&head)`, where head applies a
+    // predicate or is false. Constants other than the predicates are
+    // the clause's variables, so need not be quantified.
+    pub fn rule(&self, rule: &Ast<'ctx>) {
+        let rule = self.redirect_false_head(rule);
+        let vars = self.free_constants(&rule);
+        if vars.is_empty() {
+            self.fp.add_rule(&rule, None);
+        } else {
+            let vars : Vec<&Ast<'ctx>> = vars.iter().collect();
+            self.fp.add_rule(&Ast::forall_const(self.fp.ctx, &vars, &rule), None);
+        }
+    }
+
+    // Whether some state satisfying `bad` (over predicates and free
+    // constants, like a rule body) can be derived.
+    pub fn query(&self, bad: &Ast<'ctx>) -> ChcResult {
+        let vars = self.free_constants(bad);
+        let vars : Vec<&Ast<'ctx>> = vars.iter().collect();
+        let q = if vars.is_empty() {
+            bad.clone()
+        } else {
+            Ast::exists_const(self.fp.ctx, &vars, bad)
+        };
+        match self.fp.query(&q) {
+            SatResult::Sat => ChcResult::Unsafe,
+            SatResult::Unsat => ChcResult::Safe,
+            SatResult::Unknown => ChcResult::Unknown
+        }
+    }
+
+    // Whether the body of any rule whose head is false can be derived.
+    pub fn check(&self) -> ChcResult {
+        self.query(&self.error.apply(&[]))
+    }
+
+    // After a Safe result, the interpretation of a predicate that the
+    // rules preserve and the bad state violates. The predicate's
+    // arguments appear as bound variables: instantiate them with
+    // Ast::substitute_vars.
+    pub fn invariant(&self, pred: &FuncDecl<'ctx>) -> Option<Ast<'ctx>> {
+        self.fp.get_cover_delta(-1, pred)
+    }
+
+    pub fn invariants(&self) -> Vec<(FuncDecl<'ctx>, Ast<'ctx>)> {
+        self.predicates.borrow().iter()
+            .filter_map(|p| self.invariant(p).map(|i| (p.clone(), i)))
+            .collect()
+    }
+
+    // After an Unsafe result, a ground derivation of the bad state from
+    // the rules.
+    pub fn counterexample(&self) -> Option<Ast<'ctx>> {
+        self.fp.get_answer()
+    }
+
+    pub fn get_reason_unknown(&self) -> ReasonUnknown {
+        self.fp.get_reason_unknown()
+    }
+
+    pub fn fixedpoint(&self) -> &Fixedpoint<'ctx> {
+        &self.fp
+    }
+
+    // Spacer cannot query false, so heads that are false (or negated
+    // bodies) derive the error predicate instead.
+    fn redirect_false_head(&self, rule: &Ast<'ctx>) -> Ast<'ctx> {
+        let ctx = self.fp.ctx;
+        unsafe {
+            if Z3_get_ast_kind(ctx.z3_ctx, rule.z3_ast) == Z3_QUANTIFIER_AST {
+                if !Z3_is_quantifier_forall(ctx.z3_ctx, rule.z3_ast) {
+                    return rule.clone();
+                }
+                let body = Ast::new(ctx, Z3_get_quantifier_body(ctx.z3_ctx, rule.z3_ast));
+                return rule.update(&[self.redirect_false_head(&body)]);
+            }
+        }
+        let args = rule.app_args();
+        match rule.app_name().as_deref() {
+            Some("=>") if args[1].as_bool() == Some(false) =>
+                args[0].implies(&self.error.apply(&[])),
+            Some("not") => args[0].implies(&self.error.apply(&[])),
+            _ => rule.clone()
+        }
+    }
+
+    fn free_constants(&self, ast: &Ast<'ctx>) -> Vec<Ast<'ctx>> {
+        let mut seen = HashSet::new();
+        let mut vars = Vec::new();
+        let preds = self.predicates.borrow();
+        walk(ast, &mut seen, &mut |a| {
+            if let Some(f) = a.decl() {
+                if f.arity() == 0 && f.is_uninterpreted() &&
+                    f != self.error && !preds.contains(&f) {
+                    vars.push(a.clone());
+                }
+            }
+        });
+        vars
+    }
+}
+
+// Visits each distinct subterm once, quantifier bodies included; the
+// root keeps the subterms, and so their addresses, alive meanwhile.
+// Clauses can be far deeper than the call stack, so this keeps a stack
+// of its own.
+fn walk<'ctx, F>(ast: &Ast<'ctx>, seen: &mut HashSet<usize>, f: &mut F)
+    where F: FnMut(&Ast<'ctx>)
+{
+    let c = ast.ctx.z3_ctx;
+    let mut stack = vec![ast.clone()];
+    while let Some(a) = stack.pop() {
+        if !seen.insert(a.z3_ast as usize) {
+            continue;
+        }
+        f(&a);
+        unsafe {
+            if Z3_get_ast_kind(c, a.z3_ast) == Z3_QUANTIFIER_AST {
+                stack.push(Ast::new(a.ctx, Z3_get_quantifier_body(c, a.z3_ast)));
+            }
+        }
+        let mut args = a.app_args();
+        args.reverse();
+        stack.extend(args);
+    }
+}
+
+fn collect_predicates<'ctx, F>(ast: &Ast<'ctx>, seen: &mut HashSet<usize>,
+                               add: &mut F)
+    where F: FnMut(FuncDecl<'ctx>)
+{
+    walk(ast, seen, &mut |a| {
+        if let Some(f) = a.decl() {
+            if f.is_uninterpreted() && f.range().kind() == SortKind::Bool {
+                add(f);
+            }
+        }
+    });
+}
@@ -1,14 +1,70 @@
 use z3_sys::*;
 use Config;
-use Z3_MUTEX;
+use ConfigBuilder;
+use Encoding;
+use {version, Capability};
+use ParamKind;
+use ParamValue;
+use global_params;
+use {lock, Z3_GLOBAL_MUTEX};
 use std::ffi::CString;
 
+// What a Z3_config accepts, as documented; encoding replaced unicode
+// after 4.8.12, see Capability::Encoding. Seeds and thread counts are solver parameters instead,
+// which a Config carries separately.
+const CONTEXT_PARAMS: &[(&str, ParamKind)] = &[
+    ("auto_config", ParamKind::Bool),
+    ("debug_ref_count", ParamKind::Bool),
+    ("dot_proof_file", ParamKind::String),
+    ("dump_models", ParamKind::Bool),
+    ("encoding", ParamKind::String),
+    ("model", ParamKind::Bool),
+    ("model_validate", ParamKind::Bool),
+    ("proof", ParamKind::Bool),
+    ("rlimit", ParamKind::U32),
+    ("smtlib2_compliant", ParamKind::Bool),
+    ("stats", ParamKind::Bool),
+    ("timeout", ParamKind::U32),
+    ("trace", ParamKind::Bool),
+    ("trace_file_name", ParamKind::String),
+    ("type_check", ParamKind::Bool),
+    ("unicode", ParamKind::Bool),
+    ("unsat_core", ParamKind::Bool),
+    ("well_sorted_check", ParamKind::Bool),
+];
+
+fn check_param(k: &str, v: &str) -> Result<(), String> {
+    let kind = match CONTEXT_PARAMS.iter().find(|&&(name, _)| name == k) {
+        Some(&(_, kind)) => kind,
+        None => return Err(format!("unknown context parameter '{}'", k))
+    };
+    if k == "encoding" {
+        version().require(Capability::Encoding)?;
+    }
+    // Context parameters double as global ones, which Z3 can look up.
+    if global_params::get(k).is_none() {
+        return Err(format!("context parameter '{}' is not supported by this Z3", k));
+    }
+    let ok = match kind {
+        ParamKind::Bool => v == "true" || v == "false",
+        ParamKind::U32 => v.parse::<u32>().is_ok(),
+        _ if k == "encoding" => ["unicode", "bmp", "ascii"].contains(&v),
+        _ => true
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("invalid value '{}' for {:?} parameter '{}'", v, kind, k))
+    }
+}
+
 impl Config {
     pub fn new() -> Config {
         Config {
             kvs: Vec::new(),
+            solver_params: Vec::new(),
             z3_cfg: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
+                let guard = lock(&Z3_GLOBAL_MUTEX);
                 let p = Z3_mk_config();
                 debug!("new config {:p}", p);
                 p
@@ -20,13 +76,50 @@ impl Config {
         let vs = CString::new(v).unwrap();
         self.kvs.push((ks, vs));
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
+            let guard = lock(&Z3_GLOBAL_MUTEX);
             Z3_set_param_value(self.z3_cfg,
                                self.kvs.last().unwrap().0.as_ptr(),
                                self.kvs.last().unwrap().1.as_ptr());
         }
     }
 
+    // The value a context made from this config will use: the last one
+    // set, else the process-wide default. Solver parameters only have a
+    // value once set.
+    pub fn get_param_value(&self, k: &str) -> Option<String> {
+        if let Some((_, v)) = self.solver_params.iter().rev().find(|(n, _)| n == k) {
+            return Some(match v {
+                ParamValue::Bool(b) => b.to_string(),
+                ParamValue::U32(u) => u.to_string(),
+                ParamValue::F64(f) => f.to_string(),
+                ParamValue::Symbol(s) => s.clone()
+            });
+        }
+        let set = self.kvs.iter().rev()
+            .find(|(ks, _)| ks.to_bytes() == k.as_bytes());
+        match set {
+            Some((_, vs)) => Some(vs.to_string_lossy().into_owned()),
+            None if CONTEXT_PARAMS.iter().any(|&(name, _)| name == k) =>
+                global_params::get(k),
+            None => None
+        }
+    }
+
+    // Every context parameter this Z3 supports, with its value as above,
+    // then the solver parameters set.
+    pub fn effective_params(&self) -> Vec<(String, String)> {
+        let mut solver_params: Vec<&str> = Vec::new();
+        for (k, _) in &self.solver_params {
+            if !solver_params.contains(&k.as_str()) {
+                solver_params.push(k);
+            }
+        }
+        CONTEXT_PARAMS.iter().map(|&(k, _)| k)
+            .chain(solver_params)
+            .filter_map(|k| self.get_param_value(k).map(|v| (k.to_string(), v)))
+            .collect()
+    }
+
     pub fn set_bool_param_value(&mut self, k: &str, v: bool) {
         self.set_param_value(k, if v { "true" } else { "false" });
     }
@@ -51,13 +144,159 @@ impl Config {
     {
         self.set_param_value("timeout", &format!("{}", ms));
     }
+
+    // Seeds every solver of the context.
+    pub fn set_random_seed(&mut self, seed: u32)
+    {
+        self.solver_params.push(("random_seed".to_string(), ParamValue::U32(seed)));
+    }
+
+    // How many threads each solver of the context may use.
+    pub fn set_threads(&mut self, n: u32)
+    {
+        self.solver_params.push(("threads".to_string(), ParamValue::U32(n)));
+    }
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            params: Vec::new(),
+            solver_params: Vec::new()
+        }
+    }
+
+    // Any parameter by name; checked, like the rest, by build.
+    pub fn param(mut self, k: &str, v: &str) -> ConfigBuilder {
+        self.params.push((k.to_string(), v.to_string()));
+        self
+    }
+
+    fn bool_param(self, k: &str, b: bool) -> ConfigBuilder {
+        self.param(k, if b { "true" } else { "false" })
+    }
+
+    pub fn proof(self, b: bool) -> ConfigBuilder {
+        self.bool_param("proof", b)
+    }
+
+    pub fn model(self, b: bool) -> ConfigBuilder {
+        self.bool_param("model", b)
+    }
+
+    pub fn model_validate(self, b: bool) -> ConfigBuilder {
+        self.bool_param("model_validate", b)
+    }
+
+    pub fn unsat_core(self, b: bool) -> ConfigBuilder {
+        self.bool_param("unsat_core", b)
+    }
+
+    pub fn well_sorted_check(self, b: bool) -> ConfigBuilder {
+        self.bool_param("well_sorted_check", b)
+    }
+
+    pub fn type_check(self, b: bool) -> ConfigBuilder {
+        self.bool_param("type_check", b)
+    }
+
+    pub fn auto_config(self, b: bool) -> ConfigBuilder {
+        self.bool_param("auto_config", b)
+    }
+
+    pub fn smtlib2_compliant(self, b: bool) -> ConfigBuilder {
+        self.bool_param("smtlib2_compliant", b)
+    }
+
+    pub fn debug_ref_count(self, b: bool) -> ConfigBuilder {
+        self.bool_param("debug_ref_count", b)
+    }
+
+    pub fn dump_models(self, b: bool) -> ConfigBuilder {
+        self.bool_param("dump_models", b)
+    }
+
+    pub fn stats(self, b: bool) -> ConfigBuilder {
+        self.bool_param("stats", b)
+    }
+
+    pub fn trace(self, b: bool) -> ConfigBuilder {
+        self.bool_param("trace", b)
+    }
+
+    pub fn trace_file_name(self, name: &str) -> ConfigBuilder {
+        self.param("trace_file_name", name)
+    }
+
+    pub fn dot_proof_file(self, name: &str) -> ConfigBuilder {
+        self.param("dot_proof_file", name)
+    }
+
+    pub fn timeout_msec(self, ms: u32) -> ConfigBuilder {
+        self.param("timeout", &ms.to_string())
+    }
+
+    pub fn rlimit(self, r: u32) -> ConfigBuilder {
+        self.param("rlimit", &r.to_string())
+    }
+
+    pub fn unicode(self, b: bool) -> ConfigBuilder {
+        self.bool_param("unicode", b)
+    }
+
+    // Only for a Z3 with Capability::Encoding; build fails otherwise.
+    pub fn encoding(self, e: Encoding) -> ConfigBuilder {
+        self.param("encoding", match e {
+            Encoding::Unicode => "unicode",
+            Encoding::Bmp => "bmp",
+            Encoding::Ascii => "ascii"
+        })
+    }
+
+    // See Config::set_random_seed.
+    pub fn random_seed(mut self, seed: u32) -> ConfigBuilder {
+        self.solver_params.push(("random_seed".to_string(), ParamValue::U32(seed)));
+        self
+    }
+
+    // See Config::set_threads.
+    pub fn threads(mut self, n: u32) -> ConfigBuilder {
+        self.solver_params.push(("threads".to_string(), ParamValue::U32(n)));
+        self
+    }
+
+    // Fails on the first unknown or unsupported parameter, or value of
+    // the wrong type.
+    pub fn build(&self) -> Result<Config, String> {
+        for (k, v) in &self.params {
+            check_param(k, v)?;
+        }
+        let mut cfg = Config::new();
+        for (k, v) in &self.params {
+            cfg.set_param_value(k, v);
+        }
+        cfg.solver_params = self.solver_params.clone();
+        Ok(cfg)
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config::new()
+    }
 }
 
 impl Drop for Config {
     fn drop(&mut self) {
         unsafe {
             debug!("drop config {:p}", self.z3_cfg);
-            let guard = Z3_MUTEX.lock().unwrap();
+            let guard = lock(&Z3_GLOBAL_MUTEX);
             Z3_del_config(self.z3_cfg);
         }
     }
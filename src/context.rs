@@ -1,38 +1,151 @@
 use z3_sys::*;
 use Config;
+use Limits;
 use Context;
 use Sort;
 use Symbol;
 use Ast;
-use Z3_MUTEX;
+use FuncDecl;
+use InterruptHandle;
+use ParamDescr;
+use Capability;
+use version;
+use {lock, Z3_GLOBAL_MUTEX};
+use interrupt::InterruptState;
+use std::cell::Cell;
+use std::mem;
+use std::sync::Arc;
+use z3_string;
+
+thread_local! {
+    // How many Context::catching calls are running on this thread.
+    static CATCHING: Cell<u32> = const { Cell::new(0) };
+}
+
+// Where Z3's default handler prints the error and exits the process,
+// this panics with it, so that the caller can unwind; that is what
+// misuse, such as an unknown parameter, comes to. Inside
+// Context::catching, which the wrappers use for errors a caller cannot
+// rule out in advance, it leaves the error code in the context for the
+// wrapper to pick up instead.
+extern "C-unwind" fn on_error(c: Z3_context, e: Z3_error_code) {
+    debug!("error {} in context {:p}", e, c);
+    if CATCHING.with(|n| n.get()) == 0 {
+        let msg = unsafe { z3_string(Z3_get_error_msg(c, e)) };
+        panic!("Z3 error: {}", msg);
+    }
+}
+
+struct Catching;
+
+impl Drop for Catching {
+    fn drop(&mut self) {
+        CATCHING.with(|n| n.set(n.get() - 1));
+    }
+}
 
 impl Context {
+    // Panics on a Z3 older than Capability::BoolAbi, whose API this
+    // crate cannot call correctly.
     pub fn new(cfg: &Config) -> Context {
+        if let Err(e) = version().require(Capability::BoolAbi) {
+            panic!("{}", e);
+        }
+        let limits = Limits::of_config(cfg);
+        let p = unsafe {
+            let guard = lock(&Z3_GLOBAL_MUTEX);
+            let p = Z3_mk_context_rc(cfg.z3_cfg);
+            debug!("new context {:p}", p);
+            // z3-sys declares the handler as "C", which may not unwind.
+            let h = on_error as extern "C-unwind" fn(Z3_context, Z3_error_code);
+            Z3_set_error_handler(p, mem::transmute::<
+                extern "C-unwind" fn(Z3_context, Z3_error_code),
+                Z3_error_handler>(h));
+            p
+        };
         Context {
-            z3_ctx: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                let p = Z3_mk_context_rc(cfg.z3_cfg);
-                debug!("new context {:p}", p);
-                p
+            z3_ctx: p,
+            interrupt: Arc::new(InterruptState::new(p)),
+            limits,
+            solver_params: cfg.solver_params.clone(),
+            uninterpreted_kind: Cell::new(None)
+        }
+    }
+
+    // The declaration kind of functions declared by the user. Z3 has
+    // renumbered its kinds between versions, so this is the kind of a
+    // declaration made here, the first time it is asked for.
+    pub(crate) fn uninterpreted_kind(&self) -> Z3_decl_kind {
+        if let Some(k) = self.uninterpreted_kind.get() {
+            return k;
+        }
+        let probe = FuncDecl::new(&Symbol::from_string(self, "uninterpreted!probe"),
+                                  &[], &self.bool_sort());
+        let k = unsafe { Z3_get_decl_kind(self.z3_ctx, probe.z3_func_decl) };
+        self.uninterpreted_kind.set(Some(k));
+        k
+    }
+
+    // A handle that can be sent to other threads to interrupt checks
+    // running in this context; they then come back Unknown with
+    // ReasonUnknown::Canceled.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        InterruptHandle::of_context(self)
+    }
+
+    // Runs f, which makes Z3 calls in this context, returning the error
+    // raised by the last of them instead of panicking with it.
+    pub(crate) fn catching<T, F: FnOnce() -> T>(&self, f: F)
+                                                -> (T, Option<String>) {
+        CATCHING.with(|n| n.set(n.get() + 1));
+        let guard = Catching;
+        let t = f();
+        drop(guard);
+        (t, self.last_error())
+    }
+
+    // The error raised by the last Z3 call in this context, if any.
+    fn last_error(&self) -> Option<String> {
+        unsafe {
+            let e = Z3_get_error_code(self.z3_ctx);
+            if e == Z3_OK {
+                None
+            } else {
+                let p = Z3_get_error_msg(self.z3_ctx, e);
+                Some(z3_string(p))
             }
         }
     }
 
     // Helpers for common constructions
 
-    pub fn bool_sort(&self) -> Sort {
+    // What Ast::simplify_with accepts, as text and as a list.
+    pub fn simplify_help(&self) -> String {
+        unsafe {
+            let p = Z3_simplify_get_help(self.z3_ctx);
+            z3_string(p)
+        }
+    }
+
+    pub fn simplify_param_descrs(&self) -> Vec<ParamDescr> {
+        ParamDescr::from_z3(self, unsafe {
+            Z3_simplify_get_param_descrs(self.z3_ctx)
+        })
+    }
+
+    pub fn bool_sort<'ctx>(&'ctx self) -> Sort<'ctx> {
         Sort::bool(self)
     }
 
-    pub fn int_sort(&self) -> Sort {
+    pub fn int_sort<'ctx>(&'ctx self) -> Sort<'ctx> {
         Sort::int(self)
     }
 
-    pub fn real_sort(&self) -> Sort {
+    pub fn real_sort<'ctx>(&'ctx self) -> Sort<'ctx> {
         Sort::real(self)
     }
 
-    pub fn bitvector_sort(&self, sz: u32) -> Sort {
+    pub fn bitvector_sort<'ctx>(&'ctx self, sz: u32) -> Sort<'ctx> {
         Sort::bitvector(self, sz)
     }
 
@@ -42,15 +155,25 @@ impl Context {
         Sort::array(self, domain, range)
     }
 
+    pub fn array_sort_n<'ctx>(&'ctx self,
+                              domain: &[&Sort<'ctx>],
+                              range: &Sort<'ctx>) -> Result<Sort<'ctx>, String> {
+        Sort::array_n(self, domain, range)
+    }
+
     pub fn set_sort<'ctx>(&'ctx self, elt: &Sort<'ctx>) -> Sort<'ctx> {
         Sort::set(self, elt)
     }
 
-    pub fn int_sym(&self, i: u32) -> Symbol {
+    pub fn finite_domain_sort<'ctx>(&'ctx self, s: &str, size: u64) -> Sort<'ctx> {
+        Sort::finite_domain(self, &self.str_sym(s), size)
+    }
+
+    pub fn int_sym<'ctx>(&'ctx self, i: u32) -> Symbol<'ctx> {
         Symbol::from_int(self, i)
     }
 
-    pub fn str_sym(&self, s: &str) -> Symbol {
+    pub fn str_sym<'ctx>(&'ctx self, s: &str) -> Symbol<'ctx> {
         Symbol::from_string(self, s)
     }
 
@@ -63,70 +186,75 @@ impl Context {
     }
 
     pub fn fresh_const<'ctx>(&'ctx self, prefix: &str, sort: &'ctx Sort) -> Ast<'ctx> {
-        Ast::fresh_const(&self, prefix, sort)
+        Ast::fresh_const(self, prefix, sort)
     }
 
-    pub fn named_bool_const(&self, s: &str) -> Ast {
+    pub fn named_bool_const<'ctx>(&'ctx self, s: &str) -> Ast<'ctx> {
         Ast::new_const(&self.str_sym(s), &self.bool_sort())
     }
 
-    pub fn numbered_bool_const(&self, i: u32) -> Ast {
+    pub fn numbered_bool_const<'ctx>(&'ctx self, i: u32) -> Ast<'ctx> {
         Ast::new_const(&self.int_sym(i), &self.bool_sort())
     }
 
     pub fn fresh_bool_const<'ctx>(&'ctx self, prefix: &str) -> Ast<'ctx> {
-        Ast::fresh_const(&self, prefix, &self.bool_sort())
+        Ast::fresh_const(self, prefix, &self.bool_sort())
     }
 
-    pub fn named_int_const(&self, s: &str) -> Ast {
+    pub fn named_int_const<'ctx>(&'ctx self, s: &str) -> Ast<'ctx> {
         Ast::new_const(&self.str_sym(s), &self.int_sort())
     }
 
-    pub fn numbered_int_const(&self, i: u32) -> Ast {
+    pub fn numbered_int_const<'ctx>(&'ctx self, i: u32) -> Ast<'ctx> {
         Ast::new_const(&self.int_sym(i), &self.int_sort())
     }
 
     pub fn fresh_int_const<'ctx>(&'ctx self, prefix: &str) -> Ast<'ctx> {
-        Ast::fresh_const(&self, prefix, &self.int_sort())
+        Ast::fresh_const(self, prefix, &self.int_sort())
     }
 
-    pub fn named_real_const(&self, s: &str) -> Ast {
+    pub fn named_real_const<'ctx>(&'ctx self, s: &str) -> Ast<'ctx> {
         Ast::new_const(&self.str_sym(s), &self.real_sort())
     }
 
-    pub fn numbered_real_const(&self, i: u32) -> Ast {
+    pub fn numbered_real_const<'ctx>(&'ctx self, i: u32) -> Ast<'ctx> {
         Ast::new_const(&self.int_sym(i), &self.real_sort())
     }
 
     pub fn fresh_real_const<'ctx>(&'ctx self, prefix: &str) -> Ast<'ctx> {
-        Ast::fresh_const(&self, prefix, &self.real_sort())
+        Ast::fresh_const(self, prefix, &self.real_sort())
     }
 
-    pub fn named_bitvector_const(&self, s: &str, sz: u32) -> Ast {
+    pub fn named_bitvector_const<'ctx>(&'ctx self, s: &str, sz: u32) -> Ast<'ctx> {
         Ast::new_const(&self.str_sym(s), &self.bitvector_sort(sz))
     }
 
-    pub fn numbered_bitvector_const(&self, i: u32, sz: u32) -> Ast {
+    pub fn numbered_bitvector_const<'ctx>(&'ctx self, i: u32, sz: u32) -> Ast<'ctx> {
         Ast::new_const(&self.int_sym(i), &self.bitvector_sort(sz))
     }
 
     pub fn fresh_bitvector_const<'ctx>(&'ctx self, prefix: &str, sz: u32) -> Ast<'ctx> {
-        Ast::fresh_const(&self, prefix, &self.bitvector_sort(sz))
+        Ast::fresh_const(self, prefix, &self.bitvector_sort(sz))
+    }
+
+    pub fn func_decl<'ctx>(&'ctx self, s: &str, domain: &[&Sort<'ctx>],
+                           range: &Sort<'ctx>) -> FuncDecl<'ctx> {
+        FuncDecl::new(&self.str_sym(s), domain, range)
     }
 
-    pub fn from_bool(&self, b: bool) -> Ast {
+    pub fn from_bool<'ctx>(&'ctx self, b: bool) -> Ast<'ctx> {
         Ast::from_bool(self, b)
     }
 
-    pub fn from_u64(&self, u: u64) -> Ast {
+    pub fn from_u64<'ctx>(&'ctx self, u: u64) -> Ast<'ctx> {
         Ast::from_u64(self, u)
     }
 
-    pub fn from_i64(&self, i: i64) -> Ast {
+    pub fn from_i64<'ctx>(&'ctx self, i: i64) -> Ast<'ctx> {
         Ast::from_i64(self, i)
     }
 
-    pub fn from_real(&self, num: i32, den: i32) -> Ast {
+    pub fn from_real<'ctx>(&'ctx self, num: i32, den: i32) -> Ast<'ctx> {
         Ast::from_real(self, num, den)
     }
 }
@@ -135,6 +263,8 @@ impl Drop for Context {
     fn drop(&mut self) {
         unsafe {
             debug!("drop context {:p}", self.z3_ctx);
+            self.interrupt.detach();
+            let guard = lock(&Z3_GLOBAL_MUTEX);
             Z3_del_context(self.z3_ctx);
         }
     }
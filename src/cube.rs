@@ -0,0 +1,179 @@
+use z3_sys::*;
+use ffi::Z3_solver_cube;
+use Ast;
+use Solver;
+use Params;
+use CubeCutoff;
+use Cubes;
+use SatResult;
+use Strategy;
+use portfolio::Job;
+use Limits;
+use CancellationToken;
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use lock;
+use Capability;
+use version;
+
+impl<'ctx> Solver<'ctx> {
+    // Splits the search space into cubes that together cover it. Only
+    // the given variables are split on, or any if there are none. A
+    // problem the cuber cannot split comes back as one empty cube, and
+    // one Z3 refutes while splitting as none at all. The cutoff is
+    // honoured by SAT-based solvers, such as those for QF_FD; the default
+    // solver always splits on a single literal. The cuber is a copy, so
+    // the cutoff parameters stay off this solver.
+    pub fn cubes(&self, vars: &[&Ast<'ctx>],
+                 cutoff: CubeCutoff) -> Result<Cubes<'ctx>, String> {
+        version().require(Capability::Cubes)?;
+        let mut params = Params::new(self.ctx);
+        match cutoff {
+            CubeCutoff::Depth(d) => {
+                params.set_symbol("sat.lookahead.cube.cutoff", "depth");
+                params.set_u32("sat.lookahead.cube.depth", d);
+            }
+            CubeCutoff::FreeVars(f) => {
+                params.set_symbol("sat.lookahead.cube.cutoff", "freevars");
+                params.set_f64("sat.lookahead.cube.freevars", f);
+            }
+            CubeCutoff::Psat(t) => {
+                params.set_symbol("sat.lookahead.cube.cutoff", "psat");
+                params.set_f64("sat.lookahead.cube.psat.trigger", t);
+            }
+            CubeCutoff::AdaptiveFreeVars(f) => {
+                params.set_symbol("sat.lookahead.cube.cutoff", "adaptive_freevars");
+                params.set_f64("sat.lookahead.cube.fraction", f);
+            }
+            CubeCutoff::AdaptivePsat(f) => {
+                params.set_symbol("sat.lookahead.cube.cutoff", "adaptive_psat");
+                params.set_f64("sat.lookahead.cube.fraction", f);
+            }
+        }
+        let solver = self.translate(self.ctx);
+        solver.set_params(&params);
+        Ok(Cubes {
+            solver,
+            vars: Ast::to_z3_ast_vector(self.ctx, vars),
+            done: false
+        })
+    }
+
+    // Splits a copy of this solver into cubes and solves them on `workers`
+    // threads, each in a context of its own. Sat as soon as one cube is,
+    // along with that cube; Unsat once all of them are. Each worker gets
+    // a copy of the problem up front, and a cube at a time as it takes
+    // one off the queue, which it pushes and pops again once checked.
+    pub fn cube_and_conquer(&self, vars: &[&Ast<'ctx>], cutoff: CubeCutoff,
+                            workers: usize)
+                            -> Result<(SatResult, Option<Vec<Ast<'ctx>>>), String> {
+        let cubes: Vec<Vec<Ast<'ctx>>> = self.cubes(vars, cutoff)?.collect();
+        debug!("solving {} cubes on {} workers", cubes.len(), workers);
+        if cubes.is_empty() {
+            return Ok((SatResult::Unsat, None));
+        }
+
+        let problem = self.get_assertions();
+        let pending = Arc::new(Mutex::new(Pending {
+            src: self.ctx.z3_ctx,
+            cubes: cubes.iter().enumerate().map(|(i, cube)| {
+                (i, cube.iter().map(|a| a.z3_ast).collect())
+            }).collect()
+        }));
+        let token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel();
+        let handles: Vec<_> = (0..workers.max(1).min(cubes.len())).map(|_| {
+            let job = Job::new(&problem);
+            let pending = pending.clone();
+            let token = token.clone();
+            let tx = tx.clone();
+            thread::spawn(move || {
+                let strategy = Strategy::new("cube");
+                strategy.solve_each(job, &Limits::new(), &token, |ctx| {
+                    let mut pending = lock(&pending);
+                    let (i, cube) = pending.cubes.pop_front()?;
+                    let src = pending.src;
+                    Some((i, cube.into_iter().map(|a| {
+                        Ast::new(ctx, unsafe { Z3_translate(src, a, ctx.z3_ctx) })
+                    }).collect()))
+                }, |i, res| tx.send((i, res)).is_ok());
+            })
+        }).collect();
+        drop(tx);
+
+        // The workers translate out of this context, so it is left alone
+        // until they are joined.
+        let mut res = SatResult::Unsat;
+        let mut sat = None;
+        let mut answered = 0;
+        for (i, r) in rx.iter() {
+            answered += 1;
+            match r {
+                SatResult::Sat => {
+                    res = SatResult::Sat;
+                    sat = Some(i);
+                    break;
+                }
+                SatResult::Unknown => res = SatResult::Unknown,
+                SatResult::Unsat => ()
+            }
+        }
+        token.cancel();
+        for h in handles {
+            let _ = h.join();
+        }
+        if res == SatResult::Unsat && answered < cubes.len() {
+            res = SatResult::Unknown;
+        }
+        Ok((res, sat.map(|i| cubes[i].clone())))
+    }
+}
+
+// The cubes no worker has taken yet, as terms in the caller's context.
+// Workers translate them out of it one at a time, under the lock, while
+// the caller waits for them.
+struct Pending {
+    src: Z3_context,
+    cubes: VecDeque<(usize, Vec<Z3_ast>)>
+}
+
+unsafe impl Send for Pending {}
+
+impl<'ctx> Iterator for Cubes<'ctx> {
+    type Item = Vec<Ast<'ctx>>;
+
+    // Z3 ends the sequence with the cube false; a cube true means the
+    // remaining space could not be split further and is the last one.
+    fn next(&mut self) -> Option<Vec<Ast<'ctx>>> {
+        if self.done {
+            return None;
+        }
+        let ctx = self.solver.ctx;
+        let cube = Ast::from_z3_ast_vector(ctx, unsafe {
+            Z3_solver_cube(ctx.z3_ctx, self.solver.z3_slv, self.vars, u32::MAX)
+        });
+        if cube.len() == 1 {
+            if cube[0] == ctx.from_bool(false) {
+                self.done = true;
+                return None;
+            }
+            if cube[0] == ctx.from_bool(true) {
+                self.done = true;
+                return Some(Vec::new());
+            }
+        }
+        if cube.is_empty() {
+            self.done = true;
+        }
+        Some(cube)
+    }
+}
+
+impl<'ctx> Drop for Cubes<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            Z3_ast_vector_dec_ref(self.solver.ctx.z3_ctx, self.vars);
+        }
+    }
+}
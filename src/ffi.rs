@@ -0,0 +1,156 @@
+// Declarations for Z3 entry points that z3-sys ~0.1 either lacks or
+// declares with a signature that no longer matches the library we link.
+// Everything here follows the C API in z3_api.h and friends as of 4.8.
+
+use z3_sys::*;
+
+#[link(name = "z3")]
+extern "C" {
+    // Z3_bool became C99 bool in Z3 4.8, so these return a single byte.
+    pub fn Z3_is_eq_ast(c: Z3_context, t1: Z3_ast, t2: Z3_ast) -> bool;
+    pub fn Z3_is_eq_sort(c: Z3_context, s1: Z3_sort, s2: Z3_sort) -> bool;
+    pub fn Z3_get_numeral_small(c: Z3_context, a: Z3_ast,
+                                num: *mut i64, den: *mut i64) -> bool;
+    pub fn Z3_get_numeral_int64(c: Z3_context, v: Z3_ast, i: *mut i64)
+     -> bool;
+    pub fn Z3_get_numeral_uint64(c: Z3_context, v: Z3_ast, u: *mut u64)
+     -> bool;
+    pub fn Z3_stats_is_uint(c: Z3_context, s: Z3_stats, idx: ::libc::c_uint)
+     -> bool;
+    pub fn Z3_model_eval(c: Z3_context, m: Z3_model, t: Z3_ast,
+                         model_completion: bool, v: *mut Z3_ast) -> bool;
+    pub fn Z3_mk_goal(c: Z3_context, models: bool, unsat_cores: bool,
+                      proofs: bool) -> Z3_goal;
+    pub fn Z3_goal_is_decided_unsat(c: Z3_context, g: Z3_goal) -> bool;
+    pub fn Z3_is_quantifier_forall(c: Z3_context, a: Z3_ast) -> bool;
+    pub fn Z3_is_as_array(c: Z3_context, a: Z3_ast) -> bool;
+    pub fn Z3_global_param_get(param_id: Z3_string,
+                               param_value: *mut Z3_string) -> bool;
+    pub fn Z3_toggle_warning_messages(enabled: bool) -> ();
+
+    // Since 4.8.0 these return the asserted formulas as a vector, where
+    // z3-sys has them return their conjunction.
+    pub fn Z3_parse_smtlib2_string(c: Z3_context, s: Z3_string,
+                                   num_sorts: ::libc::c_uint,
+                                   sort_names: *const Z3_symbol,
+                                   sorts: *const Z3_sort,
+                                   num_decls: ::libc::c_uint,
+                                   decl_names: *const Z3_symbol,
+                                   decls: *const Z3_func_decl) -> Z3_ast_vector;
+    pub fn Z3_parse_smtlib2_file(c: Z3_context, file_name: Z3_string,
+                                 num_sorts: ::libc::c_uint,
+                                 sort_names: *const Z3_symbol,
+                                 sorts: *const Z3_sort,
+                                 num_decls: ::libc::c_uint,
+                                 decl_names: *const Z3_symbol,
+                                 decls: *const Z3_func_decl) -> Z3_ast_vector;
+}
+
+// Entry points that the 4.4 API z3-sys targets lacks. Binding them at load
+// time would keep the crate from loading on an older Z3 at all, so each
+// is looked up on its first call; the features using them check
+// Version::require first, so a missing one is a bug here.
+#[cfg(unix)]
+macro_rules! optional {
+    ($(pub fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {$(
+        #[allow(non_snake_case)]
+        pub unsafe extern "C" fn $name($($arg: $ty),*) -> $ret {
+            lazy_static! {
+                static ref ENTRY: usize = lookup(stringify!($name));
+            }
+            if *ENTRY == 0 {
+                panic!(concat!(stringify!($name), " is not in the linked Z3"));
+            }
+            let f = ::std::mem::transmute::<usize, unsafe extern "C" fn($($ty),*)
+                                                -> $ret>(*ENTRY);
+            f($($arg),*)
+        }
+    )*}
+}
+
+#[cfg(not(unix))]
+macro_rules! optional {
+    ($(pub fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty;)*) => {
+        #[link(name = "z3")]
+        extern "C" {
+            $(pub fn $name($($arg: $ty),*) -> $ret;)*
+        }
+    }
+}
+
+#[cfg(unix)]
+fn lookup(name: &str) -> usize {
+    let name = ::std::ffi::CString::new(name).unwrap();
+    unsafe { ::libc::dlsym(::libc::RTLD_DEFAULT, name.as_ptr()) as usize }
+}
+
+#[cfg(not(unix))]
+fn lookup(_name: &str) -> usize {
+    1
+}
+
+// Whether the linked Z3 has the given entry point.
+pub fn linked(name: &str) -> bool {
+    lookup(name) != 0
+}
+
+optional! {
+
+    pub fn Z3_mk_atleast(c: Z3_context, num_args: ::libc::c_uint,
+                         args: *const Z3_ast, k: ::libc::c_uint) -> Z3_ast;
+    pub fn Z3_mk_pble(c: Z3_context, num_args: ::libc::c_uint,
+                      args: *const Z3_ast, coeffs: *const ::libc::c_int,
+                      k: ::libc::c_int) -> Z3_ast;
+    pub fn Z3_mk_pbge(c: Z3_context, num_args: ::libc::c_uint,
+                      args: *const Z3_ast, coeffs: *const ::libc::c_int,
+                      k: ::libc::c_int) -> Z3_ast;
+    pub fn Z3_mk_pbeq(c: Z3_context, num_args: ::libc::c_uint,
+                      args: *const Z3_ast, coeffs: *const ::libc::c_int,
+                      k: ::libc::c_int) -> Z3_ast;
+
+    pub fn Z3_mk_lambda(c: Z3_context, num_decls: ::libc::c_uint,
+                        sorts: *const Z3_sort, decl_names: *const Z3_symbol,
+                        body: Z3_ast) -> Z3_ast;
+    pub fn Z3_mk_lambda_const(c: Z3_context, num_bound: ::libc::c_uint,
+                              bound: *const Z3_app, body: Z3_ast) -> Z3_ast;
+    pub fn Z3_mk_array_sort_n(c: Z3_context, n: ::libc::c_uint,
+                              domain: *const Z3_sort, range: Z3_sort)
+     -> Z3_sort;
+    pub fn Z3_mk_select_n(c: Z3_context, a: Z3_ast, n: ::libc::c_uint,
+                          idxs: *const Z3_ast) -> Z3_ast;
+    pub fn Z3_mk_store_n(c: Z3_context, a: Z3_ast, n: ::libc::c_uint,
+                         idxs: *const Z3_ast, v: Z3_ast) -> Z3_ast;
+
+    pub fn Z3_mk_set_has_size(c: Z3_context, set: Z3_ast, k: Z3_ast) -> Z3_ast;
+
+    pub fn Z3_get_full_version() -> Z3_string;
+
+    pub fn Z3_param_descrs_get_documentation(c: Z3_context, p: Z3_param_descrs,
+                                             s: Z3_symbol) -> Z3_string;
+    pub fn Z3_model_translate(c: Z3_context, m: Z3_model, dst: Z3_context)
+     -> Z3_model;
+    pub fn Z3_solver_from_string(c: Z3_context, s: Z3_solver, smt2: Z3_string)
+     -> ();
+    pub fn Z3_solver_get_consequences(c: Z3_context, s: Z3_solver,
+                                      assumptions: Z3_ast_vector,
+                                      variables: Z3_ast_vector,
+                                      consequences: Z3_ast_vector) -> Z3_lbool;
+    pub fn Z3_solver_cube(c: Z3_context, s: Z3_solver, vars: Z3_ast_vector,
+                          backtrack_level: ::libc::c_uint) -> Z3_ast_vector;
+    pub fn Z3_solver_interrupt(c: Z3_context, s: Z3_solver) -> ();
+    pub fn Z3_optimize_check(c: Z3_context, o: Z3_optimize,
+                             num_assumptions: ::libc::c_uint,
+                             assumptions: *const Z3_ast) -> Z3_lbool;
+    pub fn Z3_optimize_get_unsat_core(c: Z3_context, o: Z3_optimize)
+     -> Z3_ast_vector;
+    pub fn Z3_optimize_get_assertions(c: Z3_context, o: Z3_optimize)
+     -> Z3_ast_vector;
+    pub fn Z3_optimize_get_objectives(c: Z3_context, o: Z3_optimize)
+     -> Z3_ast_vector;
+    pub fn Z3_optimize_get_lower_as_vector(c: Z3_context, o: Z3_optimize,
+                                           idx: ::libc::c_uint)
+     -> Z3_ast_vector;
+    pub fn Z3_optimize_get_upper_as_vector(c: Z3_context, o: Z3_optimize,
+                                           idx: ::libc::c_uint)
+     -> Z3_ast_vector;
+}
@@ -0,0 +1,234 @@
+use z3_sys::*;
+use Context;
+use Fixedpoint;
+use FixedpointEngine;
+use FuncDecl;
+use Params;
+use Symbol;
+use Ast;
+use SatResult;
+use ReasonUnknown;
+use Capability;
+use version;
+use ffi::{Z3_parse_smtlib2_string, Z3_parse_smtlib2_file};
+use z3_string;
+use z3_len;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt;
+use std::ptr;
+
+impl<'ctx> Fixedpoint<'ctx> {
+    pub fn new(ctx: &'ctx Context) -> Fixedpoint<'ctx> {
+        Fixedpoint {
+            ctx,
+            z3_fp: unsafe {
+                let fp = Z3_mk_fixedpoint(ctx.z3_ctx);
+                Z3_fixedpoint_inc_ref(ctx.z3_ctx, fp);
+                fp
+            },
+            reason: RefCell::new(None)
+        }
+    }
+
+    // Datalog wants relations over finite domains or bit-vectors; Spacer
+    // takes arithmetic too, but does not enumerate answers.
+    pub fn set_engine(&self, engine: FixedpointEngine) {
+        let mut params = Params::new(self.ctx);
+        params.set_symbol("engine", match engine {
+            FixedpointEngine::Datalog => "datalog",
+            FixedpointEngine::Spacer => "spacer"
+        });
+        self.set_params(&params);
+    }
+
+    pub fn set_params(&self, params: &Params<'ctx>) {
+        unsafe {
+            Z3_fixedpoint_set_params(self.ctx.z3_ctx, self.z3_fp,
+                                     params.z3_params);
+        }
+    }
+
+    pub fn register_relation(&self, f: &FuncDecl<'ctx>) {
+        unsafe {
+            Z3_fixedpoint_register_relation(self.ctx.z3_ctx, self.z3_fp,
+                                            f.z3_func_decl);
+        }
+    }
+
+    // A Horn clause, usually universally quantified over its variables
+    // (see Ast::forall_const); a bare application is a fact.
+    pub fn add_rule(&self, rule: &Ast<'ctx>, name: Option<&str>) {
+        let sym = name.map(|n| Symbol::from_string(self.ctx, n));
+        unsafe {
+            Z3_fixedpoint_add_rule(self.ctx.z3_ctx, self.z3_fp, rule.z3_ast,
+                                   sym.as_ref().map_or(ptr::null_mut(),
+                                                       |s| s.z3_sym));
+        }
+    }
+
+    // A ground fact over finite-domain (or bit-vector) arguments, given
+    // as element numbers.
+    pub fn add_fact(&self, relation: &FuncDecl<'ctx>, args: &[u32]) {
+        let mut tmp : Vec<::libc::c_uint> = args.to_vec();
+        unsafe {
+            Z3_fixedpoint_add_fact(self.ctx.z3_ctx, self.z3_fp,
+                                   relation.z3_func_decl,
+                                   z3_len(&tmp),
+                                   tmp.as_mut_ptr());
+        }
+    }
+
+    // Background axioms that are not rules.
+    pub fn assert(&self, ast: &Ast<'ctx>) {
+        unsafe {
+            Z3_fixedpoint_assert(self.ctx.z3_ctx, self.z3_fp, ast.z3_ast);
+        }
+    }
+
+    // Sat when the query, an existentially closed formula, is derivable
+    // from the rules; get_answer then tells how.
+    pub fn query(&self, query: &Ast<'ctx>) -> SatResult {
+        self.run(|| unsafe {
+            Z3_fixedpoint_query(self.ctx.z3_ctx, self.z3_fp, query.z3_ast)
+        })
+    }
+
+    // Sat when any of the relations is non-empty.
+    pub fn query_relations(&self, relations: &[&FuncDecl<'ctx>]) -> SatResult {
+        let tmp : Vec<Z3_func_decl> = relations.iter()
+            .map(|r| r.z3_func_decl).collect();
+        self.run(|| unsafe {
+            Z3_fixedpoint_query_relations(self.ctx.z3_ctx, self.z3_fp,
+                                          z3_len(&tmp),
+                                          tmp.as_ptr())
+        })
+    }
+
+    fn run<F>(&self, query: F) -> SatResult
+        where F: FnOnce() -> Z3_lbool
+    {
+        let (res, canceled) = self.ctx.interrupt.run_check(None, query);
+        *self.reason.borrow_mut() =
+            if canceled { Some(ReasonUnknown::Canceled) } else { None };
+        res
+    }
+
+    // After a query: for Datalog, the derivable instances of the query
+    // as a formula when Sat; for Spacer, a derivation when Sat and an
+    // inductive invariant when Unsat.
+    pub fn get_answer(&self) -> Option<Ast<'ctx>> {
+        let (a, err) = self.ctx.catching(|| unsafe {
+            Z3_fixedpoint_get_answer(self.ctx.z3_ctx, self.z3_fp)
+        });
+        if err.is_some() || a.is_null() {
+            None
+        } else {
+            Some(Ast::new(self.ctx, a))
+        }
+    }
+
+    pub fn get_reason_unknown(&self) -> ReasonUnknown {
+        if let Some(ref r) = *self.reason.borrow() {
+            return r.clone();
+        }
+        ReasonUnknown::from_z3_string(&z3_string(unsafe {
+            Z3_fixedpoint_get_reason_unknown(self.ctx.z3_ctx, self.z3_fp)
+        }))
+    }
+
+    pub fn get_rules(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_fixedpoint_get_rules(self.ctx.z3_ctx, self.z3_fp)
+        })
+    }
+
+    pub fn get_assertions(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_fixedpoint_get_assertions(self.ctx.z3_ctx, self.z3_fp)
+        })
+    }
+
+    // Reads an SMT-LIB2 script, such as a Horn problem in the HORN
+    // logic, and returns its assertions for the caller to add as rules,
+    // assertions or queries. The error is Z3's message for an unreadable
+    // file or a parse error, with the line and column.
+    pub fn from_file(&self, path: &str) -> Result<Vec<Ast<'ctx>>, String> {
+        version().require(Capability::ParserVectors)?;
+        let p = CString::new(path).unwrap();
+        self.parsed(self.ctx.catching(|| unsafe {
+            Z3_parse_smtlib2_file(self.ctx.z3_ctx, p.as_ptr(), 0, ptr::null(),
+                                  ptr::null(), 0, ptr::null(), ptr::null())
+        }))
+    }
+
+    pub fn from_string(&self, s: &str) -> Result<Vec<Ast<'ctx>>, String> {
+        version().require(Capability::ParserVectors)?;
+        let s = CString::new(s).unwrap();
+        self.parsed(self.ctx.catching(|| unsafe {
+            Z3_parse_smtlib2_string(self.ctx.z3_ctx, s.as_ptr(), 0, ptr::null(),
+                                    ptr::null(), 0, ptr::null(), ptr::null())
+        }))
+    }
+
+    // Z3 reports a parse error as (error "...").
+    fn parsed(&self, (v, err): (Z3_ast_vector, Option<String>))
+              -> Result<Vec<Ast<'ctx>>, String> {
+        match err {
+            Some(e) => {
+                let e = e.trim();
+                Err(e.strip_prefix("(error \"").and_then(|m| m.strip_suffix("\")"))
+                    .unwrap_or(e).to_string())
+            }
+            None if v.is_null() => Err("parser error".to_string()),
+            None => Ok(Ast::from_z3_ast_vector(self.ctx, v))
+        }
+    }
+
+    // The property Spacer has established for a relation at the given
+    // unfolding level, -1 being the fixedpoint; its arguments appear as
+    // bound variables (see Ast::substitute_vars).
+    pub fn get_cover_delta(&self, level: i32,
+                           relation: &FuncDecl<'ctx>) -> Option<Ast<'ctx>> {
+        let (a, err) = self.ctx.catching(|| unsafe {
+            Z3_fixedpoint_get_cover_delta(self.ctx.z3_ctx, self.z3_fp, level,
+                                          relation.z3_func_decl)
+        });
+        if err.is_some() || a.is_null() {
+            None
+        } else {
+            Some(Ast::new(self.ctx, a))
+        }
+    }
+
+    pub fn push(&self) {
+        unsafe {
+            Z3_fixedpoint_push(self.ctx.z3_ctx, self.z3_fp);
+        }
+    }
+
+    pub fn pop(&self) {
+        unsafe {
+            Z3_fixedpoint_pop(self.ctx.z3_ctx, self.z3_fp);
+        }
+    }
+}
+
+// The rules and assertions in SMT-LIB2 syntax.
+impl<'ctx> fmt::Display for Fixedpoint<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = z3_string(unsafe {
+            Z3_fixedpoint_to_string(self.ctx.z3_ctx, self.z3_fp, 0,
+                                    ptr::null_mut())
+        });
+        write!(f, "{}", s)
+    }
+}
+
+impl<'ctx> Drop for Fixedpoint<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            Z3_fixedpoint_dec_ref(self.ctx.z3_ctx, self.z3_fp);
+        }
+    }
+}
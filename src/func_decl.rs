@@ -0,0 +1,108 @@
+use z3_sys::*;
+use Context;
+use FuncDecl;
+use Symbol;
+use Sort;
+use Ast;
+use z3_string;
+use z3_len;
+
+impl<'ctx> FuncDecl<'ctx> {
+    pub(crate) fn from_z3(ctx: &'ctx Context, f: Z3_func_decl) -> FuncDecl<'ctx> {
+        assert!(!f.is_null());
+        FuncDecl {
+            ctx,
+            z3_func_decl: unsafe {
+                debug!("new func decl {:p}", f);
+                Z3_inc_ref(ctx.z3_ctx, Z3_func_decl_to_ast(ctx.z3_ctx, f));
+                f
+            }
+        }
+    }
+
+    pub fn new(sym: &Symbol<'ctx>, domain: &[&Sort<'ctx>],
+               range: &Sort<'ctx>) -> FuncDecl<'ctx> {
+        let tmp : Vec<Z3_sort> = domain.iter().map(|s| s.z3_sort).collect();
+        FuncDecl::from_z3(sym.ctx, unsafe {
+            Z3_mk_func_decl(sym.ctx.z3_ctx, sym.z3_sym,
+                            z3_len(&tmp), tmp.as_ptr(),
+                            range.z3_sort)
+        })
+    }
+
+    pub fn arity(&self) -> u32 {
+        unsafe {
+            Z3_get_arity(self.ctx.z3_ctx, self.z3_func_decl)
+        }
+    }
+
+    pub fn name(&self) -> String {
+        unsafe {
+            let sym = Z3_get_decl_name(self.ctx.z3_ctx, self.z3_func_decl);
+            z3_string(Z3_get_symbol_string(self.ctx.z3_ctx, sym))
+        }
+    }
+
+    pub fn symbol(&self) -> Symbol<'ctx> {
+        Symbol::from_z3(self.ctx, unsafe {
+            Z3_get_decl_name(self.ctx.z3_ctx, self.z3_func_decl)
+        })
+    }
+
+    // Declared by the user rather than built into Z3.
+    pub fn is_uninterpreted(&self) -> bool {
+        let ctx = self.ctx;
+        unsafe {
+            Z3_get_decl_kind(ctx.z3_ctx, self.z3_func_decl) ==
+                ctx.uninterpreted_kind()
+        }
+    }
+
+    pub fn range(&self) -> Sort<'ctx> {
+        Sort::from_z3(self.ctx, unsafe {
+            Z3_get_range(self.ctx.z3_ctx, self.z3_func_decl)
+        })
+    }
+
+    pub fn apply(&self, args: &[&Ast<'ctx>]) -> Ast<'ctx> {
+        let tmp : Vec<Z3_ast> = args.iter().map(|a| a.z3_ast).collect();
+        Ast::new(self.ctx, unsafe {
+            Z3_mk_app(self.ctx.z3_ctx, self.z3_func_decl,
+                      z3_len(&tmp), tmp.as_ptr())
+        })
+    }
+
+    pub fn translate<'dest>(&self, dest: &'dest Context) -> FuncDecl<'dest> {
+        FuncDecl::from_z3(dest, unsafe {
+            let a = Z3_translate(self.ctx.z3_ctx,
+                                 Z3_func_decl_to_ast(self.ctx.z3_ctx,
+                                                     self.z3_func_decl),
+                                 dest.z3_ctx);
+            Z3_to_func_decl(dest.z3_ctx, a)
+        })
+    }
+}
+
+impl<'ctx> Clone for FuncDecl<'ctx> {
+    fn clone(&self) -> FuncDecl<'ctx> {
+        FuncDecl::from_z3(self.ctx, self.z3_func_decl)
+    }
+}
+
+impl<'ctx> PartialEq<FuncDecl<'ctx>> for FuncDecl<'ctx> {
+    fn eq(&self, other: &FuncDecl<'ctx>) -> bool {
+        self.z3_func_decl == other.z3_func_decl
+    }
+}
+
+impl<'ctx> Eq for FuncDecl<'ctx> { }
+
+impl<'ctx> Drop for FuncDecl<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            debug!("drop func decl {:p}", self.z3_func_decl);
+            Z3_dec_ref(self.ctx.z3_ctx,
+                       Z3_func_decl_to_ast(self.ctx.z3_ctx, self.z3_func_decl));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Z3's process-wide parameters: the top-level ones such as verbose and
+// timeout, and module defaults such as pp.decimal or sat.random_seed.
+// Contexts pick these up when created; printing options apply at once.
+// Z3 keeps them in unsynchronized global state, hence the global mutex.
+
+use z3_sys::*;
+use ffi::{Z3_global_param_get, Z3_toggle_warning_messages};
+use {lock, Z3_GLOBAL_MUTEX};
+use ParamDescr;
+use ParamKind;
+use z3_string;
+use std::ffi::CString;
+use std::ptr;
+
+// The current value, or None for a name Z3 does not know.
+pub fn get(name: &str) -> Option<String> {
+    let guard = lock(&Z3_GLOBAL_MUTEX);
+    quietly(|| lookup(name))
+}
+
+// Runs f with Z3's warnings off: it would warn on stderr about unknown
+// names and ill-typed values, which the caller hears about anyway.
+// Call with the global mutex held.
+fn quietly<T, F: FnOnce() -> T>(f: F) -> T {
+    let warn = lookup("warning").as_deref() != Some("false");
+    unsafe {
+        Z3_toggle_warning_messages(false);
+        let v = f();
+        Z3_toggle_warning_messages(warn);
+        v
+    }
+}
+
+// Call with the global mutex held; the value lives in a shared buffer.
+fn lookup(name: &str) -> Option<String> {
+    let n = CString::new(name).unwrap();
+    let mut v = ptr::null();
+    unsafe {
+        if Z3_global_param_get(n.as_ptr(), &mut v) {
+            Some(z3_string(v))
+        } else {
+            None
+        }
+    }
+}
+
+// Whether Z3's rendering of a value is the value given. Z3 prints
+// numbers its own way: without leading zeros, and doubles to six
+// significant digits.
+fn same_value(now: &str, given: &str) -> bool {
+    if now.eq_ignore_ascii_case(given) {
+        return true;
+    }
+    match (now.parse::<f64>(), given.parse::<f64>()) {
+        (Ok(a), Ok(b)) => (a - b).abs() <= 1e-5 * a.abs().max(b.abs()),
+        _ => false
+    }
+}
+
+pub fn get_bool(name: &str) -> Option<bool> {
+    get(name).and_then(|v| v.parse().ok())
+}
+
+pub fn get_u32(name: &str) -> Option<u32> {
+    get(name).and_then(|v| v.parse().ok())
+}
+
+pub fn get_f64(name: &str) -> Option<f64> {
+    get(name).and_then(|v| v.parse().ok())
+}
+
+// Z3 itself only warns about unknown names and ill-typed values, and
+// ignores them; here they are errors, and leave the parameter as it was.
+pub fn set(name: &str, value: &str) -> Result<(), String> {
+    replace(name, value).map(|_| ())
+}
+
+// Sets the parameter and returns its previous value.
+fn replace(name: &str, value: &str) -> Result<String, String> {
+    let n = CString::new(name).unwrap();
+    let v = CString::new(value).unwrap();
+    let guard = lock(&Z3_GLOBAL_MUTEX);
+    quietly(|| {
+        let old = lookup(name).ok_or_else(|| format!("unknown parameter '{}'", name))?;
+        unsafe {
+            Z3_global_param_set(n.as_ptr(), v.as_ptr());
+        }
+        match lookup(name) {
+            Some(ref now) if same_value(now, value) => Ok(old),
+            _ => {
+                let o = CString::new(old).unwrap();
+                unsafe {
+                    Z3_global_param_set(n.as_ptr(), o.as_ptr());
+                }
+                Err(format!("invalid value '{}' for parameter '{}'", value, name))
+            }
+        }
+    })
+}
+
+pub fn set_bool(name: &str, value: bool) -> Result<(), String> {
+    set(name, &value.to_string())
+}
+
+pub fn set_u32(name: &str, value: u32) -> Result<(), String> {
+    set(name, &value.to_string())
+}
+
+pub fn set_f64(name: &str, value: f64) -> Result<(), String> {
+    set(name, &value.to_string())
+}
+
+// Back to Z3's defaults, for every parameter.
+pub fn reset_all() {
+    let guard = lock(&Z3_GLOBAL_MUTEX);
+    unsafe {
+        Z3_global_param_reset_all();
+    }
+}
+
+// Sets parameters for as long as it lives, then puts back the values
+// they had before.
+pub struct Scoped {
+    saved: Vec<(String, String)>
+}
+
+pub fn scoped() -> Scoped {
+    Scoped { saved: Vec::new() }
+}
+
+impl Scoped {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        let old = replace(name, value)?;
+        if !self.saved.iter().any(|(n, _)| n == name) {
+            self.saved.push((name.to_string(), old));
+        }
+        Ok(())
+    }
+
+    pub fn set_bool(&mut self, name: &str, value: bool) -> Result<(), String> {
+        self.set(name, &value.to_string())
+    }
+
+    pub fn set_u32(&mut self, name: &str, value: u32) -> Result<(), String> {
+        self.set(name, &value.to_string())
+    }
+
+    pub fn set_f64(&mut self, name: &str, value: f64) -> Result<(), String> {
+        self.set(name, &value.to_string())
+    }
+}
+
+impl Drop for Scoped {
+    fn drop(&mut self) {
+        for (name, value) in self.saved.iter().rev() {
+            let _ = set(name, value);
+        }
+    }
+}
+
+// Z3 offers no listing of its global parameters, so this is the set
+// documented for 4.8, less whatever the linked library does not know.
+// Any module parameter (smt.*, sat.*, ...) can be set globally too; see
+// the parameter descriptions of solvers and tactics for those.
+const KNOWN: &[(&str, ParamKind, &str)] = &[
+    ("auto_config", ParamKind::Bool,
+     "use heuristics to automatically select solver and configure it"),
+    ("debug_ref_count", ParamKind::Bool, "debug support for AST reference counting"),
+    ("dot_proof_file", ParamKind::String, "file in which to output graphical proofs"),
+    ("dump_models", ParamKind::Bool, "dump models whenever check-sat returns sat"),
+    ("memory_high_watermark", ParamKind::U32, "set high watermark for memory consumption (in bytes)"),
+    ("memory_max_alloc_count", ParamKind::U32, "set hard upper limit for memory allocations"),
+    ("memory_max_size", ParamKind::U32, "set hard upper limit for memory consumption (in megabytes)"),
+    ("model", ParamKind::Bool, "model generation for solvers"),
+    ("model_validate", ParamKind::Bool, "validate models produced by solvers"),
+    ("proof", ParamKind::Bool, "proof generation"),
+    ("rlimit", ParamKind::U32, "default resource limit used for solvers; unrestricted when 0"),
+    ("smtlib2_compliant", ParamKind::Bool, "enable/disable SMT-LIB 2.0 compliance"),
+    ("stats", ParamKind::Bool, "enable/disable statistics"),
+    ("timeout", ParamKind::U32, "timeout in milliseconds; no timeout when 4294967295"),
+    ("trace", ParamKind::Bool, "trace generation for VCC"),
+    ("trace_file_name", ParamKind::String, "trace out file name"),
+    ("type_check", ParamKind::Bool, "type checker (alias for well_sorted_check)"),
+    ("unsat_core", ParamKind::Bool, "unsat-core generation for solvers"),
+    ("verbose", ParamKind::U32, "be verbose, where the value is the verbosity level"),
+    ("warning", ParamKind::Bool, "enable/disable warning messages"),
+    ("well_sorted_check", ParamKind::Bool, "type checker"),
+    ("pp.bv_literals", ParamKind::Bool, "use bit-vector literals when possible"),
+    ("pp.bv_neg", ParamKind::Bool, "use bvneg when displaying bit-vector literals with the most significant bit set"),
+    ("pp.decimal", ParamKind::Bool, "pretty print real numbers using decimal notation"),
+    ("pp.decimal_precision", ParamKind::U32, "maximum number of decimal places to be used when pp.decimal is true"),
+    ("pp.fixed_indent", ParamKind::Bool, "use a fixed indentation for applications"),
+    ("pp.flat_assoc", ParamKind::Bool, "flat associative operators when pretty printing"),
+    ("pp.max_depth", ParamKind::U32, "max. term depth when pretty printing expressions"),
+    ("pp.max_indent", ParamKind::U32, "max. indentation in pretty printer"),
+    ("pp.max_width", ParamKind::U32, "max. width in pretty printer"),
+    ("pp.min_alias_size", ParamKind::U32, "min. size for creating an alias for a shared term"),
+    ("pp.pretty_proof", ParamKind::Bool, "use slower, but prettier, printer for proofs"),
+    ("pp.single_line", ParamKind::Bool, "ignore line breaks when true"),
+];
+
+pub fn known() -> Vec<ParamDescr> {
+    KNOWN.iter()
+        .filter(|&&(name, _, _)| get(name).is_some())
+        .map(|&(name, kind, doc)| ParamDescr {
+            name: name.to_string(),
+            kind,
+            doc: doc.to_string()
+        })
+        .collect()
+}
@@ -0,0 +1,236 @@
+use z3_sys::*;
+use ffi::Z3_solver_interrupt;
+use Context;
+use InterruptHandle;
+use CancellationToken;
+use SatResult;
+use lock;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Z3 handles are plain pointers; the context and solvers they name are
+// only touched through Z3's thread-safe interrupt entry points.
+struct Handles {
+    z3_ctx: Option<Z3_context>,
+    running: Vec<Z3_solver>
+}
+
+unsafe impl Send for Handles {}
+
+// Shared between a Context and its InterruptHandles. The context pointer
+// is cleared before the context is deleted, and solvers are only listed
+// while one of their checks is on the stack, so an interrupt can never
+// reach a dead handle.
+pub(crate) struct InterruptState {
+    handles: Mutex<Handles>,
+    requested: AtomicBool
+}
+
+impl InterruptState {
+    pub(crate) fn new(z3_ctx: Z3_context) -> InterruptState {
+        InterruptState {
+            handles: Mutex::new(Handles {
+                z3_ctx: Some(z3_ctx),
+                running: Vec::new()
+            }),
+            requested: AtomicBool::new(false)
+        }
+    }
+
+    pub(crate) fn detach(&self) {
+        lock(&self.handles).z3_ctx = None;
+    }
+
+    fn interrupt(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        let handles = lock(&self.handles);
+        if let Some(c) = handles.z3_ctx {
+            unsafe {
+                Z3_interrupt(c);
+                for s in &handles.running {
+                    Z3_solver_interrupt(c, *s);
+                }
+            }
+        }
+    }
+
+    // Runs a check, reporting whether it was canceled. An interrupt that
+    // arrives while no check is running cancels the next one instead of
+    // being dropped. The flag is only taken once the solver is listed, so
+    // a later interrupt finds it running rather than slipping in between.
+    pub(crate) fn run_check<F>(&self, slv: Option<Z3_solver>,
+                               check: F) -> (SatResult, bool)
+        where F: FnOnce() -> Z3_lbool
+    {
+        if let Some(s) = slv {
+            lock(&self.handles).running.push(s);
+        }
+        let pending = self.requested.swap(false, Ordering::SeqCst);
+        let res = if pending {
+            SatResult::Unknown
+        } else {
+            SatResult::from_z3(check())
+        };
+        if let Some(s) = slv {
+            lock(&self.handles).running.retain(|r| *r != s);
+        }
+        let canceled = pending || self.requested.swap(false, Ordering::SeqCst);
+        (res, canceled && res == SatResult::Unknown)
+    }
+}
+
+impl InterruptHandle {
+    pub(crate) fn of_context(ctx: &Context) -> InterruptHandle {
+        InterruptHandle {
+            state: ctx.interrupt.clone()
+        }
+    }
+
+    // Interrupts whatever check is running in the context, or the next one
+    // to start if none is. Safe to call from any thread, and a no-op once
+    // the context is gone.
+    pub fn interrupt(&self) {
+        self.state.interrupt();
+    }
+
+    pub fn is_alive(&self) -> bool {
+        lock(&self.state.handles).z3_ctx.is_some()
+    }
+}
+
+pub(crate) struct TokenState {
+    canceled: AtomicBool,
+    handles: Mutex<Vec<InterruptHandle>>
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            state: Arc::new(TokenState {
+                canceled: AtomicBool::new(false),
+                handles: Mutex::new(Vec::new())
+            })
+        }
+    }
+
+    // Adds a context to the group canceled by this token; registering
+    // with a token that is already canceled interrupts straight away.
+    pub fn register(&self, ctx: &Context) {
+        let handle = ctx.interrupt_handle();
+        let mut handles = lock(&self.state.handles);
+        handles.retain(|h| h.is_alive());
+        if self.is_canceled() {
+            handle.interrupt();
+        }
+        handles.push(handle);
+    }
+
+    pub fn cancel(&self) {
+        self.state.canceled.store(true, Ordering::SeqCst);
+        let mut handles = lock(&self.state.handles);
+        handles.retain(|h| h.is_alive());
+        for h in handles.iter() {
+            h.interrupt();
+        }
+    }
+
+    pub fn is_canceled(&self) -> bool {
+        self.state.canceled.load(Ordering::SeqCst)
+    }
+
+    // Cancels this token when the process receives SIGINT, for as long
+    // as any clone of it is alive. The signal handler only writes to a
+    // pipe; a helper thread does the canceling.
+    #[cfg(unix)]
+    pub fn cancel_on_sigint(&self) {
+        sigint::watch(self);
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+#[cfg(unix)]
+mod sigint {
+    use CancellationToken;
+    use super::TokenState;
+    use lock;
+    use std::sync::{Arc, Mutex, Once, Weak};
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::thread;
+    use std::io;
+
+    // Held weakly, so that watching does not keep a token alive; dropped
+    // tokens are pruned whenever another one is added.
+    lazy_static! {
+        static ref TOKENS: Mutex<Vec<Weak<TokenState>>> = Mutex::new(Vec::new());
+    }
+
+    static INSTALL: Once = Once::new();
+    static WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+    extern "C" fn on_sigint(_: ::libc::c_int) {
+        let fd = WRITE_FD.load(Ordering::SeqCst);
+        if fd >= 0 {
+            let b = 1u8;
+            unsafe {
+                ::libc::write(fd, &b as *const u8 as *const ::libc::c_void, 1);
+            }
+        }
+    }
+
+    pub fn watch(token: &CancellationToken) {
+        {
+            let mut tokens = lock(&TOKENS);
+            tokens.retain(|t| t.upgrade().is_some());
+            let weak = Arc::downgrade(&token.state);
+            if !tokens.iter().any(|t| t.ptr_eq(&weak)) {
+                tokens.push(weak);
+            }
+        }
+        INSTALL.call_once(|| {
+            let mut fds = [0 as ::libc::c_int; 2];
+            if unsafe { ::libc::pipe(fds.as_mut_ptr()) } != 0 {
+                warn!("unable to create SIGINT pipe");
+                return;
+            }
+            let read_fd = fds[0];
+            thread::spawn(move || {
+                let mut b = 0u8;
+                loop {
+                    let n = unsafe {
+                        ::libc::read(read_fd,
+                                     &mut b as *mut u8 as *mut ::libc::c_void, 1)
+                    };
+                    if n == 0 {
+                        break;
+                    }
+                    if n < 0 {
+                        if io::Error::last_os_error().kind() ==
+                            io::ErrorKind::Interrupted {
+                            continue;
+                        }
+                        break;
+                    }
+                    debug!("SIGINT received, canceling");
+                    let live: Vec<CancellationToken> = lock(&TOKENS).iter()
+                        .filter_map(|t| t.upgrade())
+                        .map(|state| CancellationToken { state })
+                        .collect();
+                    for t in &live {
+                        t.cancel();
+                    }
+                }
+            });
+            WRITE_FD.store(fds[1], Ordering::SeqCst);
+            unsafe {
+                ::libc::signal(::libc::SIGINT,
+                               on_sigint as extern "C" fn(::libc::c_int)
+                               as ::libc::sighandler_t);
+            }
+        });
+    }
+}
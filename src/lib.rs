@@ -11,32 +11,261 @@ extern crate lazy_static;
 extern crate z3_sys;
 extern crate libc;
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::cell::{Cell, RefCell};
+use std::time::{Duration, Instant};
 use std::ffi::CString;
+use std::rc::Rc;
+use std::collections::HashSet;
 use z3_sys::*;
 
+mod ffi;
 mod sort;
 mod config;
 mod context;
 mod symbol;
 mod ast;
+mod func_decl;
 mod solver;
 mod optimize;
+mod params;
 mod model;
+mod interrupt;
+mod limits;
+mod sat_result;
+mod portfolio;
+mod proof;
+mod cube;
+mod fixedpoint;
+mod chc;
+pub mod global_params;
+mod version;
 
-// Z3 appears to be only mostly-threadsafe, a few initializers
-// and such race; so we mutex-guard all access to the library.
+// Z3 is threadsafe across contexts except for a few initializers and
+// other process-wide state, which race; so we mutex-guard only those.
+// Everything else is confined to its context, which is never Sync.
 lazy_static! {
-    static ref Z3_MUTEX: Mutex<()> = Mutex::new(());
+    static ref Z3_GLOBAL_MUTEX: Mutex<()> = Mutex::new(());
+}
+
+// A panic while holding one of our locks leaves nothing half-updated
+// behind it, so a poisoned lock is as good as any other.
+pub(crate) fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Copies out a string Z3 handed back. A call that failed, for instance
+// because its context was interrupted part way, returns null instead,
+// which reads as empty.
+pub(crate) fn z3_string(p: Z3_string) -> String {
+    if p.is_null() {
+        String::new()
+    } else {
+        unsafe { std::ffi::CStr::from_ptr(p).to_string_lossy().into_owned() }
+    }
+}
+
+// The length of a slice handed to Z3, which counts in unsigned ints.
+pub(crate) fn z3_len<T>(s: &[T]) -> ::libc::c_uint {
+    assert!(s.len() <= ::libc::c_uint::MAX as usize);
+    s.len() as ::libc::c_uint
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SatResult {
+    Unsat,
+    Unknown,
+    Sat
+}
+
+// Why a check came back Unknown, where Z3 or this crate can tell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReasonUnknown {
+    Canceled,
+    Timeout,
+    ResourceLimit,
+    MemoryLimit,
+    Other(String)
+}
+
+// The version of the Z3 library linked in, which need not be the one
+// z3-sys was written against.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    major: u32,
+    minor: u32,
+    build: u32,
+    revision: u32,
+    full: String
+}
+
+// Features that need a Z3 newer than some of the builds this crate may
+// be linked against; see Version::supports. Anything older than BoolAbi
+// needs no entry, since Context::new requires it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    Lambda,
+    ArrayN,
+    Cubes,
+    Spacer,
+    // The single-byte Z3_bool (C99 bool) that src/ffi.rs assumes.
+    BoolAbi,
+    // The Z3_OP_PR_* numbering ProofNode::rule decodes.
+    ProofRuleKinds,
+    // SMT-LIB2 parsers returning each assertion rather than their
+    // conjunction, as Fixedpoint::from_string reads them.
+    ParserVectors,
+    // Ast::set_has_size.
+    SetHasSize,
+    // The encoding context parameter, set by ConfigBuilder::encoding.
+    Encoding
+}
+
+lazy_static! {
+    static ref VERSION: Version = Version::linked();
+}
+
+pub fn version() -> Version {
+    VERSION.clone()
+}
+
+// Limits applied to a single check by Solver::check_with_limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    timeout: Option<Duration>,
+    rlimit: Option<u32>,
+    max_memory_mb: Option<u32>
+}
+
+// A deadline shared out across a sequence of checks.
+#[derive(Clone, Debug)]
+pub struct TimeBudget {
+    deadline: Instant,
+    checks_left: u32
 }
 
 pub struct Config {
     kvs: Vec<(CString,CString)>,
+    // Solver parameters that Z3 does not take in a config, applied to
+    // each solver of a context made from this one.
+    solver_params: Vec<(String, ParamValue)>,
     z3_cfg: Z3_config
 }
 
+// Collects context parameters for a Config, checking them against the
+// parameters the linked Z3 knows when built.
+pub struct ConfigBuilder {
+    params: Vec<(String, String)>,
+    solver_params: Vec<(String, ParamValue)>
+}
+
 pub struct Context {
-    z3_ctx: Z3_context
+    z3_ctx: Z3_context,
+    interrupt: Arc<interrupt::InterruptState>,
+    limits: Limits,
+    solver_params: Vec<(String, ParamValue)>,
+    uninterpreted_kind: Cell<Option<Z3_decl_kind>>
+}
+
+// A context may move to another thread once nothing borrows it, and so
+// with all its objects; it is deliberately not Sync.
+unsafe impl Send for Context {}
+
+// Interrupts checks in a Context from any thread; see Context::interrupt_handle.
+#[derive(Clone)]
+pub struct InterruptHandle {
+    state: Arc<interrupt::InterruptState>
+}
+
+// Cancels a whole group of contexts at once.
+#[derive(Clone)]
+pub struct CancellationToken {
+    state: Arc<interrupt::TokenState>
+}
+
+// A context-free parameter value, for settings that have to cross
+// threads before there is a context to build Params in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParamValue {
+    Bool(bool),
+    U32(u32),
+    F64(f64),
+    Symbol(String)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParamKind {
+    U32,
+    Bool,
+    F64,
+    Symbol,
+    String,
+    Other
+}
+
+// The character set of string literals, for ConfigBuilder::encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Unicode,
+    Bmp,
+    Ascii
+}
+
+// A parameter a Z3 component accepts, as listed by Z3 itself.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParamDescr {
+    name: String,
+    kind: ParamKind,
+    doc: String
+}
+
+// One configuration raced by a Portfolio.
+#[derive(Clone, Debug)]
+pub struct Strategy {
+    name: String,
+    logic: Option<String>,
+    tactic: Option<String>,
+    seed: Option<u32>,
+    params: Vec<(String, ParamValue)>
+}
+
+// Races the same assertions under several strategies, each in its own
+// context on its own thread.
+pub struct Portfolio<'ctx>
+{
+    ctx: &'ctx Context,
+    assertions: Vec<Ast<'ctx>>,
+    strategies: Vec<Strategy>,
+    limits: Limits
+}
+
+pub struct PortfolioResult<'ctx>
+{
+    result: SatResult,
+    winner: Option<usize>,
+    name: Option<String>,
+    model: Option<Model<'ctx>>,
+    elapsed: Duration
+}
+
+pub struct Params<'ctx>
+{
+    ctx: &'ctx Context,
+    z3_params: Z3_params,
+    values: Vec<(String, ParamValue)>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Int,
+    Str
+}
+
+// A symbol's name, detached from the context.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolName {
+    Int(u32),
+    Str(String)
 }
 
 pub struct Symbol<'ctx>
@@ -46,6 +275,24 @@ pub struct Symbol<'ctx>
     z3_sym: Z3_symbol
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SortKind {
+    Uninterpreted,
+    Bool,
+    Int,
+    Real,
+    BitVector,
+    Array,
+    Datatype,
+    Relation,
+    FiniteDomain,
+    FloatingPoint,
+    RoundingMode,
+    Seq,
+    Re,
+    Unknown
+}
+
 pub struct Sort<'ctx>
 {
     ctx: &'ctx Context,
@@ -58,10 +305,152 @@ pub struct Ast<'ctx>
     z3_ast: Z3_ast
 }
 
+pub struct FuncDecl<'ctx>
+{
+    ctx: &'ctx Context,
+    z3_func_decl: Z3_func_decl
+}
+
+// The inference rule behind a proof step, from the kind of its declaration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProofRule {
+    Undef,
+    True,
+    Asserted,
+    Goal,
+    ModusPonens,
+    Reflexivity,
+    Symmetry,
+    Transitivity,
+    TransitivityStar,
+    Monotonicity,
+    QuantIntro,
+    Bind,
+    Distributivity,
+    AndElim,
+    NotOrElim,
+    Rewrite,
+    RewriteStar,
+    PullQuant,
+    PushQuant,
+    ElimUnusedVars,
+    Der,
+    QuantInst,
+    Hypothesis,
+    Lemma,
+    UnitResolution,
+    IffTrue,
+    IffFalse,
+    Commutativity,
+    DefAxiom,
+    AssumptionAdd,
+    LemmaAdd,
+    RedundantDel,
+    ClauseTrail,
+    DefIntro,
+    ApplyDef,
+    IffOeq,
+    NnfPos,
+    NnfNeg,
+    Skolemize,
+    ModusPonensOeq,
+    ThLemma,
+    HyperResolve,
+    // A rule this crate does not know, or any rule when the linked Z3
+    // lacks Capability::ProofRuleKinds; the Z3_decl_kind.
+    Other(u32)
+}
+
+// One step of a proof. Z3 shares subproofs, and so do nodes: a premise
+// used twice is the same Rc both times.
+pub struct ProofNode<'ctx>
+{
+    rule: ProofRule,
+    name: String,
+    premises: Vec<Rc<ProofNode<'ctx>>>,
+    conclusion: Ast<'ctx>,
+    proof: Ast<'ctx>
+}
+
+// A fact Solver::consequences found: under the assumptions, the
+// variable can only take this value.
+pub struct Consequence<'ctx>
+{
+    assumptions: Vec<Ast<'ctx>>,
+    variable: Ast<'ctx>,
+    value: Ast<'ctx>,
+    ast: Ast<'ctx>
+}
+
 pub struct Solver<'ctx>
 {
     ctx: &'ctx Context,
-    z3_slv: Z3_solver
+    z3_slv: Z3_solver,
+    limits: Cell<Limits>,
+    reason: RefCell<Option<ReasonUnknown>>
+}
+
+// When Solver::cubes stops splitting; these are the lookahead cutoffs
+// of Z3's SAT solver.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CubeCutoff {
+    Depth(u32),
+    FreeVars(f64),
+    Psat(f64),
+    AdaptiveFreeVars(f64),
+    AdaptivePsat(f64)
+}
+
+// Iterates over the cubes of a copy of a solver, each a conjunction of
+// literals; see Solver::cubes.
+pub struct Cubes<'ctx>
+{
+    solver: Solver<'ctx>,
+    vars: Z3_ast_vector,
+    done: bool
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FixedpointEngine {
+    Datalog,
+    Spacer
+}
+
+pub struct Fixedpoint<'ctx>
+{
+    ctx: &'ctx Context,
+    z3_fp: Z3_fixedpoint,
+    reason: RefCell<Option<ReasonUnknown>>
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChcResult {
+    Safe,
+    Unsafe,
+    Unknown
+}
+
+// A system of constrained Horn clauses, solved by Spacer: rules over
+// uninterpreted predicates, and a bad state that is either reachable
+// (with a derivation to show for it) or excluded by an invariant.
+pub struct Chc<'ctx>
+{
+    fp: Fixedpoint<'ctx>,
+    predicates: RefCell<Vec<FuncDecl<'ctx>>>,
+    error: FuncDecl<'ctx>
+}
+
+// Enumerates the models of a solver, one per distinct valuation of the
+// projection terms; see Solver::models. Pops the scope it pushed when
+// dropped.
+pub struct Models<'a, 'ctx: 'a>
+{
+    solver: &'a Solver<'ctx>,
+    terms: Vec<Ast<'ctx>>,
+    limit: Option<usize>,
+    found: usize,
+    done: bool,
+    complete: bool
 }
 
 pub struct Model<'ctx>
@@ -70,9 +459,70 @@ pub struct Model<'ctx>
     z3_mdl: Z3_model
 }
 
+// The value of a set in a model: its members when there are finitely
+// many, otherwise the finitely many elements it lacks.
+pub enum SetValue<'ctx> {
+    Finite(HashSet<Ast<'ctx>>),
+    Cofinite(HashSet<Ast<'ctx>>)
+}
+
 pub struct Optimize<'ctx>
 {
     ctx: &'ctx Context,
-    z3_opt: Z3_optimize
+    z3_opt: Z3_optimize,
+    id: usize,
+    priority: Cell<Priority>,
+    reason: RefCell<Option<ReasonUnknown>>
+}
+
+// How an optimizer with several objectives trades them off against
+// each other; this is Z3's `opt.priority`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    Lexicographic,
+    Pareto,
+    Box
+}
+
+// Yields successive Pareto-optimal models until the front is exhausted.
+// Puts back the optimizer's previous priority when dropped.
+pub struct ParetoFront<'a, 'ctx: 'a>
+{
+    opt: &'a Optimize<'ctx>,
+    prev: Priority,
+    done: bool
+}
+
+// Pops the scope it pushed when dropped.
+pub struct OptimizeScope<'a, 'ctx: 'a>
+{
+    opt: &'a Optimize<'ctx>
+}
+
+// An objective of the Optimize that created it, which alone can read
+// its bounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Objective
+{
+    opt: usize,
+    idx: u32
+}
+
+// An objective bound is a * infinity + b + c * epsilon; the infinite
+// cases carry no finite part and epsilon only shows up with strict
+// inequalities over the reals.
+pub enum Bound<'ctx>
+{
+    NegInfinity,
+    Finite(Ast<'ctx>),
+    Epsilon(Ast<'ctx>, Ast<'ctx>),
+    PosInfinity
+}
+
+pub struct SoftConstraint<'ctx>
+{
+    ast: Ast<'ctx>,
+    group: Option<String>,
+    obj: Objective
 }
 
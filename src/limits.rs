@@ -0,0 +1,164 @@
+use Limits;
+use TimeBudget;
+use ReasonUnknown;
+use Config;
+use Context;
+use Params;
+use std::cmp;
+use std::time::{Duration, Instant};
+
+impl Limits {
+    pub fn new() -> Limits {
+        Limits {
+            timeout: None,
+            rlimit: None,
+            max_memory_mb: None
+        }
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Limits {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Z3's deterministic resource counter; unlike a timeout, the same
+    // problem exhausts the same rlimit on every run and machine.
+    pub fn rlimit(mut self, rlimit: u32) -> Limits {
+        self.rlimit = Some(rlimit);
+        self
+    }
+
+    pub fn max_memory_mb(mut self, mb: u32) -> Limits {
+        self.max_memory_mb = Some(mb);
+        self
+    }
+
+    // The limits every solver of a context starts with, as its config
+    // sets them; Z3 takes u32::MAX for no timeout and 0 for no rlimit.
+    pub(crate) fn of_config(cfg: &Config) -> Limits {
+        let get = |k| cfg.get_param_value(k).and_then(|v| v.parse::<u32>().ok());
+        Limits {
+            timeout: get("timeout").filter(|&ms| ms != u32::MAX)
+                .map(|ms| Duration::from_millis(ms as u64)),
+            rlimit: get("rlimit").filter(|&r| r != 0),
+            max_memory_mb: None
+        }
+    }
+
+    pub(crate) fn params<'ctx>(&self, ctx: &'ctx Context) -> Params<'ctx> {
+        let mut params = Params::new(ctx);
+        if let Some(ms) = self.timeout_msec() {
+            params.set_u32("timeout", ms);
+        }
+        if let Some(r) = self.rlimit {
+            params.set_u32("rlimit", r);
+        }
+        if let Some(mb) = self.max_memory_mb {
+            params.set_u32("max_memory", mb);
+        }
+        params
+    }
+
+    // Params that put these limits back for each one `changed` sets, an
+    // absent limit by turning it off.
+    pub(crate) fn restore_params<'ctx>(&self, ctx: &'ctx Context,
+                                       changed: &Limits) -> Params<'ctx> {
+        let mut params = Params::new(ctx);
+        if changed.timeout.is_some() {
+            params.set_u32("timeout", self.timeout_msec().unwrap_or(u32::MAX));
+        }
+        if changed.rlimit.is_some() {
+            params.set_u32("rlimit", self.rlimit.unwrap_or(0));
+        }
+        if changed.max_memory_mb.is_some() {
+            params.set_u32("max_memory", self.max_memory_mb.unwrap_or(u32::MAX));
+        }
+        params
+    }
+
+    // Follows whatever limits a solver's params set.
+    pub(crate) fn update(&mut self, params: &Params) {
+        if let Some(ms) = params.get_u32("timeout") {
+            self.timeout = Some(ms).filter(|&ms| ms != u32::MAX)
+                .map(|ms| Duration::from_millis(ms as u64));
+        }
+        if let Some(r) = params.get_u32("rlimit") {
+            self.rlimit = Some(r).filter(|&r| r != 0);
+        }
+        if let Some(mb) = params.get_u32("max_memory") {
+            self.max_memory_mb = Some(mb).filter(|&mb| mb != u32::MAX);
+        }
+    }
+
+    pub(crate) fn timeout_msec(&self) -> Option<u32> {
+        self.timeout.map(|d| {
+            cmp::min(d.as_millis(), (u32::MAX - 1) as u128) as u32
+        })
+    }
+
+    // Z3 4.8 names a timeout or running out of memory, but reports a
+    // spent rlimit as "canceled", and some solvers only ever say
+    // "unknown". Take what Z3 names, and otherwise work out which limit
+    // ran out from what the check used. Memory is only blamed when Z3
+    // says so, as the peak statistic is for the whole process.
+    pub(crate) fn exhausted(&self, reported: ReasonUnknown, elapsed: Duration,
+                            rlimit_used: f64) -> Option<ReasonUnknown> {
+        match reported {
+            ReasonUnknown::Timeout |
+            ReasonUnknown::ResourceLimit |
+            ReasonUnknown::MemoryLimit => return Some(reported),
+            _ => ()
+        }
+        if let Some(r) = self.rlimit {
+            if rlimit_used >= r as f64 {
+                return Some(ReasonUnknown::ResourceLimit);
+            }
+        }
+        if let Some(t) = self.timeout {
+            if elapsed >= t {
+                return Some(ReasonUnknown::Timeout);
+            }
+        }
+        None
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits::new()
+    }
+}
+
+impl TimeBudget {
+    // A budget of `total` to be spread over about `checks` checks.
+    pub fn new(total: Duration, checks: u32) -> TimeBudget {
+        TimeBudget {
+            deadline: Instant::now() + total,
+            checks_left: checks
+        }
+    }
+
+    pub fn remaining(&self) -> Duration {
+        let now = Instant::now();
+        if now >= self.deadline {
+            Duration::from_secs(0)
+        } else {
+            self.deadline - now
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() < Duration::from_millis(1)
+    }
+
+    // An even share of what is left for the next check; once the expected
+    // number of checks is used up, each further check may take it all.
+    pub fn next_timeout(&mut self) -> Option<Duration> {
+        if self.is_exhausted() {
+            return None;
+        }
+        let share = self.remaining() / cmp::max(self.checks_left, 1);
+        self.checks_left = self.checks_left.saturating_sub(1);
+        Some(cmp::max(share, Duration::from_millis(1)))
+    }
+}
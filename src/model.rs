@@ -1,48 +1,149 @@
 use z3_sys::*;
+use ffi::{Z3_model_eval, Z3_model_translate, Z3_is_as_array};
+use Context;
 use Solver;
 use Optimize;
 use Model;
 use Ast;
-use Z3_MUTEX;
+use FuncDecl;
+use SetValue;
+use z3_string;
+use std::collections::HashSet;
+use std::fmt;
 
 impl<'ctx> Model<'ctx> {
-    pub fn of_solver(slv: &Solver<'ctx>) -> Model<'ctx> {
+    // The model of the last check, None unless it came back Sat (or
+    // Unknown with a candidate model).
+    pub fn of_solver(slv: &Solver<'ctx>) -> Option<Model<'ctx>> {
+        Model::of_z3(slv.ctx, slv.ctx.catching(|| unsafe {
+            Z3_solver_get_model(slv.ctx.z3_ctx, slv.z3_slv)
+        }))
+    }
+
+    pub fn of_optimize(opt: &Optimize<'ctx>) -> Option<Model<'ctx>> {
+        Model::of_z3(opt.ctx, opt.ctx.catching(|| unsafe {
+            Z3_optimize_get_model(opt.ctx.z3_ctx, opt.z3_opt)
+        }))
+    }
+
+    fn of_z3(ctx: &'ctx Context, (m, err): (Z3_model, Option<String>))
+             -> Option<Model<'ctx>> {
+        if let Some(e) = err {
+            debug!("no model: {}", e);
+            return None;
+        }
+        if m.is_null() {
+            return None;
+        }
+        unsafe {
+            Z3_model_inc_ref(ctx.z3_ctx, m);
+        }
+        Some(Model { ctx, z3_mdl: m })
+    }
+
+    pub fn translate<'dest>(&self, dest: &'dest Context) -> Model<'dest> {
         Model {
-            ctx: slv.ctx,
+            ctx: dest,
             z3_mdl: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                let m = Z3_solver_get_model(slv.ctx.z3_ctx, slv.z3_slv);
-                Z3_model_inc_ref(slv.ctx.z3_ctx, m);
+                let m = Z3_model_translate(self.ctx.z3_ctx,
+                                           self.z3_mdl,
+                                           dest.z3_ctx);
+                Z3_model_inc_ref(dest.z3_ctx, m);
                 m
             }
         }
     }
 
-    pub fn of_optimize(opt: &Optimize<'ctx>) -> Model<'ctx> {
-        Model {
-            ctx: opt.ctx,
-            z3_mdl: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                let m = Z3_optimize_get_model(opt.ctx.z3_ctx, opt.z3_opt);
-                Z3_model_inc_ref(opt.ctx.z3_ctx, m);
-                m
+    // Reads back the value of a set, as the stores over a constant
+    // array, or the function interpretation, that models give sets.
+    // None for values of any other shape, such as lambdas.
+    #[allow(clippy::mutable_key_type)]
+    pub fn eval_set(&self, set: &Ast<'ctx>) -> Option<SetValue<'ctx>> {
+        let mut v = self.eval(set)?;
+        let mut seen = HashSet::new();
+        let mut members = HashSet::new();
+        let mut excluded = HashSet::new();
+        let mut put = |elt: Ast<'ctx>, val: Option<bool>| -> Option<()> {
+            if seen.insert(elt.clone()) {
+                if val? { members.insert(elt); } else { excluded.insert(elt); }
+            }
+            Some(())
+        };
+        // Outermost stores first: they shadow the ones beneath.
+        let full = loop {
+            if unsafe { Z3_is_as_array(self.ctx.z3_ctx, v.z3_ast) } {
+                let (entries, default) = self.func_interp(&v)?;
+                for (elt, val) in entries {
+                    put(elt, val.as_bool())?;
+                }
+                break default.as_bool()?;
             }
+            let args = v.app_args();
+            match v.app_name().as_deref() {
+                Some("store") if args.len() == 3 => {
+                    put(args[1].clone(), args[2].as_bool())?;
+                    v = args[0].clone();
+                }
+                Some("const") if args.len() == 1 => break args[0].as_bool()?,
+                _ => return None
+            }
+        };
+        Some(if full {
+            SetValue::Cofinite(excluded)
+        } else {
+            SetValue::Finite(members)
+        })
+    }
+
+    // The entries and else value behind an as-array term for a unary
+    // function.
+    fn func_interp(&self, as_array: &Ast<'ctx>)
+                   -> Option<(Vec<(Ast<'ctx>, Ast<'ctx>)>, Ast<'ctx>)> {
+        let c = self.ctx.z3_ctx;
+        unsafe {
+            let f = Z3_get_as_array_func_decl(c, as_array.z3_ast);
+            let fi = Z3_model_get_func_interp(c, self.z3_mdl, f);
+            if fi.is_null() {
+                return None;
+            }
+            Z3_func_interp_inc_ref(c, fi);
+            if Z3_func_interp_get_arity(c, fi) != 1 {
+                Z3_func_interp_dec_ref(c, fi);
+                return None;
+            }
+            let entries = (0..Z3_func_interp_get_num_entries(c, fi)).map(|i| {
+                let e = Z3_func_interp_get_entry(c, fi, i);
+                Z3_func_entry_inc_ref(c, e);
+                let arg = Ast::new(self.ctx, Z3_func_entry_get_arg(c, e, 0));
+                let val = Ast::new(self.ctx, Z3_func_entry_get_value(c, e));
+                Z3_func_entry_dec_ref(c, e);
+                (arg, val)
+            }).collect();
+            let default = Ast::new(self.ctx, Z3_func_interp_get_else(c, fi));
+            Z3_func_interp_dec_ref(c, fi);
+            Some((entries, default))
+        }
+    }
+
+    // The constants this model assigns; see FuncDecl::symbol for their
+    // names.
+    pub fn consts(&self) -> Vec<FuncDecl<'ctx>> {
+        unsafe {
+            (0..Z3_model_get_num_consts(self.ctx.z3_ctx, self.z3_mdl))
+                .map(|i| FuncDecl::from_z3(self.ctx, Z3_model_get_const_decl(
+                    self.ctx.z3_ctx, self.z3_mdl, i)))
+                .collect()
         }
     }
 
     pub fn eval(&self, ast: &Ast<'ctx>) -> Option<Ast<'ctx>> {
         unsafe {
             let mut tmp : Z3_ast = ast.z3_ast;
-            let res;
-            {
-                let guard = Z3_MUTEX.lock().unwrap();
-                res = Z3_model_eval(self.ctx.z3_ctx,
-                                    self.z3_mdl,
-                                    ast.z3_ast,
-                                    Z3_TRUE,
-                                    &mut tmp)
-            }
-            if res == Z3_TRUE {
+            if Z3_model_eval(self.ctx.z3_ctx,
+                             self.z3_mdl,
+                             ast.z3_ast,
+                             true,
+                             &mut tmp) {
                 Some(Ast::new(self.ctx, tmp))
             } else {
                 None
@@ -51,10 +152,35 @@ impl<'ctx> Model<'ctx> {
     }
 }
 
+impl<'ctx> SetValue<'ctx> {
+    pub fn contains(&self, elt: &Ast<'ctx>) -> bool {
+        match *self {
+            SetValue::Finite(ref members) => members.contains(elt),
+            SetValue::Cofinite(ref excluded) => !excluded.contains(elt)
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        match *self {
+            SetValue::Finite(_) => true,
+            SetValue::Cofinite(_) => false
+        }
+    }
+}
+
+impl<'ctx> fmt::Display for Model<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = unsafe {
+            let p = Z3_model_to_string(self.ctx.z3_ctx, self.z3_mdl);
+            z3_string(p)
+        };
+        write!(f, "{}", s)
+    }
+}
+
 impl<'ctx> Drop for Model<'ctx> {
     fn drop(&mut self) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_model_dec_ref(self.ctx.z3_ctx, self.z3_mdl);
         }
     }
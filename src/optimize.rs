@@ -1,67 +1,363 @@
 use z3_sys::*;
+use ffi::{Z3_optimize_check,
+          Z3_optimize_get_unsat_core,
+          Z3_optimize_get_assertions,
+          Z3_optimize_get_objectives,
+          Z3_optimize_get_lower_as_vector,
+          Z3_optimize_get_upper_as_vector};
 use Context;
 use Optimize;
+use OptimizeScope;
+use ParetoFront;
+use Params;
+use Priority;
+use SatResult;
+use ReasonUnknown;
+use Objective;
+use Bound;
+use SoftConstraint;
+use Symbol;
 use Model;
 use Ast;
-use Z3_MUTEX;
+use std::ffi::CString;
+use std::ptr;
+use std::ops::Deref;
+use z3_string;
+use z3_len;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tells optimizers apart for the handles they give out; unlike the
+// Z3_optimize pointer, an id is never reused.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
 
 impl<'ctx> Optimize<'ctx> {
     pub fn new(ctx: &'ctx Context) -> Optimize<'ctx> {
         Optimize {
-            ctx: ctx,
+            ctx,
             z3_opt: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 let opt = Z3_mk_optimize(ctx.z3_ctx);
                 Z3_optimize_inc_ref(ctx.z3_ctx, opt);
                 opt
-            }
+            },
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            priority: Cell::new(Priority::Lexicographic),
+            reason: RefCell::new(None)
         }
     }
 
     pub fn assert(&self, ast: &Ast<'ctx>) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_optimize_assert(self.ctx.z3_ctx,
                                self.z3_opt,
                                ast.z3_ast);
         }
     }
 
-    pub fn maximize(&self, ast: &Ast<'ctx>) {
+    // Soft constraints may be violated at a cost of their weight; constraints
+    // sharing a group are minimized together as a single MaxSMT objective.
+    pub fn assert_soft(&self, ast: &Ast<'ctx>, weight: u64,
+                       group: Option<&str>) -> SoftConstraint<'ctx> {
+        self.assert_soft_weighted(ast, &format!("{}", weight), group)
+    }
+
+    pub fn assert_soft_real(&self, ast: &Ast<'ctx>, num: u64, den: u64,
+                            group: Option<&str>) -> SoftConstraint<'ctx> {
+        self.assert_soft_weighted(ast, &format!("{}/{}", num, den), group)
+    }
+
+    fn assert_soft_weighted(&self, ast: &Ast<'ctx>, weight: &str,
+                            group: Option<&str>) -> SoftConstraint<'ctx> {
+        let ws = CString::new(weight).unwrap();
+        let sym = group.map(|g| Symbol::from_string(self.ctx, g));
+        let idx = unsafe {
+            Z3_optimize_assert_soft(self.ctx.z3_ctx,
+                                    self.z3_opt,
+                                    ast.z3_ast,
+                                    ws.as_ptr(),
+                                    match sym {
+                                        Some(ref s) => s.z3_sym,
+                                        None => ptr::null_mut()
+                                    })
+        };
+        SoftConstraint {
+            ast: Ast::new(self.ctx, ast.z3_ast),
+            group: group.map(|g| g.to_string()),
+            obj: Objective { opt: self.id, idx }
+        }
+    }
+
+    pub fn maximize(&self, ast: &Ast<'ctx>) -> Objective {
+        Objective {
+            opt: self.id,
+            idx: unsafe {
+                Z3_optimize_maximize(self.ctx.z3_ctx,
+                                     self.z3_opt,
+                                     ast.z3_ast)
+            }
+        }
+    }
+
+    pub fn minimize(&self, ast: &Ast<'ctx>) -> Objective {
+        Objective {
+            opt: self.id,
+            idx: unsafe {
+                Z3_optimize_minimize(self.ctx.z3_ctx,
+                                     self.z3_opt,
+                                     ast.z3_ast)
+            }
+        }
+    }
+
+    pub fn check(&self) -> SatResult {
+        self.check_assumptions(&[])
+    }
+
+    // Checks the hard constraints together with the given Boolean
+    // assumptions; if that is unsat, get_unsat_core names the culprits.
+    pub fn check_assumptions(&self, assumptions: &[&Ast<'ctx>]) -> SatResult {
+        let tmp : Vec<Z3_ast> = assumptions.iter().map(|a| a.z3_ast).collect();
+        let (res, canceled) =
+            self.ctx.interrupt.run_check(None, || unsafe {
+                Z3_optimize_check(self.ctx.z3_ctx,
+                                  self.z3_opt,
+                                  z3_len(&tmp),
+                                  tmp.as_ptr())
+            });
+        *self.reason.borrow_mut() =
+            if canceled { Some(ReasonUnknown::Canceled) } else { None };
+        res
+    }
+
+    pub fn get_reason_unknown(&self) -> ReasonUnknown {
+        if let Some(ref r) = *self.reason.borrow() {
+            return r.clone();
+        }
+        ReasonUnknown::from_z3_string(&unsafe {
+            let p = Z3_optimize_get_reason_unknown(self.ctx.z3_ctx,
+                                                   self.z3_opt);
+            z3_string(p)
+        })
+    }
+
+    pub fn get_unsat_core(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_optimize_get_unsat_core(self.ctx.z3_ctx, self.z3_opt)
+        })
+    }
+
+    pub fn get_assertions(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_optimize_get_assertions(self.ctx.z3_ctx, self.z3_opt)
+        })
+    }
+
+    pub fn get_objectives(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_optimize_get_objectives(self.ctx.z3_ctx, self.z3_opt)
+        })
+    }
+
+    pub fn set_params(&self, params: &Params<'ctx>) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            Z3_optimize_maximize(self.ctx.z3_ctx,
-                                 self.z3_opt,
-                                 ast.z3_ast);
+            Z3_optimize_set_params(self.ctx.z3_ctx,
+                                   self.z3_opt,
+                                   params.z3_params);
         }
     }
 
-    pub fn minimize(&self, ast: &Ast<'ctx>) {
+    pub fn set_priority(&self, priority: Priority) {
+        let mut params = Params::new(self.ctx);
+        params.set_symbol("priority", match priority {
+            Priority::Lexicographic => "lex",
+            Priority::Pareto => "pareto",
+            Priority::Box => "box"
+        });
+        self.set_params(&params);
+        self.priority.set(priority);
+    }
+
+    // Switches to Pareto mode; each step of the iterator is another
+    // `check`, yielding the model and the value of every objective at it,
+    // in the order the objectives were added. The priority last chosen
+    // with set_priority, lexicographic by default, is back in force once
+    // the iterator is dropped.
+    pub fn pareto_front<'a>(&'a self) -> ParetoFront<'a, 'ctx> {
+        let prev = self.priority.get();
+        self.set_priority(Priority::Pareto);
+        ParetoFront { opt: self, prev, done: false }
+    }
+
+    pub fn push(&self) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            Z3_optimize_minimize(self.ctx.z3_ctx,
-                                 self.z3_opt,
-                                 ast.z3_ast);
+            Z3_optimize_push(self.ctx.z3_ctx, self.z3_opt);
         }
     }
 
-    pub fn check(&self) -> bool {
+    pub fn pop(&self) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            Z3_optimize_check(self.ctx.z3_ctx,
-                              self.z3_opt) == Z3_TRUE
+            Z3_optimize_pop(self.ctx.z3_ctx, self.z3_opt);
         }
     }
 
-    pub fn get_model(&self) -> Model<'ctx> {
+    // Pushes a scope that is popped again when the returned guard drops;
+    // the guard derefs to the optimizer for adding scoped constraints.
+    pub fn scope<'a>(&'a self) -> OptimizeScope<'a, 'ctx> {
+        self.push();
+        OptimizeScope { opt: self }
+    }
+
+    pub fn get_model(&self) -> Option<Model<'ctx>> {
         Model::of_optimize(self)
     }
+
+    // Bounds on an objective as of the last `check`; once that succeeds
+    // the bound facing the optimization direction is the optimum. An
+    // objective of another optimizer is an error.
+    pub fn get_lower(&self, obj: &Objective) -> Result<Bound<'ctx>, String> {
+        self.get_lower_as_vector(obj).map(Bound::from_vector)
+    }
+
+    pub fn get_upper(&self, obj: &Objective) -> Result<Bound<'ctx>, String> {
+        self.get_upper_as_vector(obj).map(Bound::from_vector)
+    }
+
+    // The raw [a, b, c] numerals of a bound a * infinity + b + c * epsilon.
+    pub fn get_lower_as_vector(&self, obj: &Objective)
+                               -> Result<Vec<Ast<'ctx>>, String> {
+        let idx = self.objective_index(obj)?;
+        Ok(Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_optimize_get_lower_as_vector(self.ctx.z3_ctx, self.z3_opt, idx)
+        }))
+    }
+
+    pub fn get_upper_as_vector(&self, obj: &Objective)
+                               -> Result<Vec<Ast<'ctx>>, String> {
+        let idx = self.objective_index(obj)?;
+        Ok(Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_optimize_get_upper_as_vector(self.ctx.z3_ctx, self.z3_opt, idx)
+        }))
+    }
+
+    // Objectives are numbered per optimizer, so another one's index would
+    // name a different objective here, or none at all.
+    fn objective_index(&self, obj: &Objective) -> Result<u32, String> {
+        if obj.opt == self.id {
+            Ok(obj.idx)
+        } else {
+            Err("objective belongs to a different Optimize".to_string())
+        }
+    }
+
+    // The penalty is the summed weight of the violated soft constraints
+    // in the group that `soft` belongs to, as of the last `check`.
+    pub fn get_penalty(&self, soft: &SoftConstraint<'ctx>)
+                       -> Result<Ast<'ctx>, String> {
+        let idx = self.objective_index(&soft.obj)?;
+        Ok(Ast::new(self.ctx, unsafe {
+            Z3_optimize_get_lower(self.ctx.z3_ctx, self.z3_opt, idx)
+        }))
+    }
+}
+
+impl<'a, 'ctx> Deref for OptimizeScope<'a, 'ctx> {
+    type Target = Optimize<'ctx>;
+
+    fn deref(&self) -> &Optimize<'ctx> {
+        self.opt
+    }
+}
+
+impl<'a, 'ctx> Drop for OptimizeScope<'a, 'ctx> {
+    fn drop(&mut self) {
+        self.opt.pop();
+    }
+}
+
+impl<'a, 'ctx> Drop for ParetoFront<'a, 'ctx> {
+    fn drop(&mut self) {
+        self.opt.set_priority(self.prev);
+    }
+}
+
+impl<'a, 'ctx> Iterator for ParetoFront<'a, 'ctx> {
+    type Item = (Model<'ctx>, Vec<Bound<'ctx>>);
+
+    fn next(&mut self) -> Option<(Model<'ctx>, Vec<Bound<'ctx>>)> {
+        if self.done {
+            return None;
+        }
+        if self.opt.check() != SatResult::Sat {
+            self.done = true;
+            return None;
+        }
+        // get_objectives hands back terms normalized for minimization,
+        // while the bounds at a Pareto point keep each objective's sign.
+        let n = self.opt.get_objectives().len() as u32;
+        let values = (0..n)
+            .map(|idx| Objective { opt: self.opt.id, idx })
+            .map(|obj| Bound::from_vector(self.opt.get_lower_as_vector(&obj)
+                                          .expect("an objective of this Optimize")))
+            .collect();
+        Some((self.opt.get_model()?, values))
+    }
+}
+
+impl<'ctx> Bound<'ctx> {
+    fn from_vector(v: Vec<Ast<'ctx>>) -> Bound<'ctx> {
+        assert!(v.len() == 3);
+        let mut it = v.into_iter();
+        let inf = it.next().unwrap();
+        let val = it.next().unwrap();
+        let eps = it.next().unwrap();
+        match inf.as_i64() {
+            Some(n) if n < 0 => Bound::NegInfinity,
+            Some(n) if n > 0 => Bound::PosInfinity,
+            _ => if eps.as_i64() == Some(0) {
+                Bound::Finite(val)
+            } else {
+                Bound::Epsilon(val, eps)
+            }
+        }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        matches!(*self, Bound::Finite(_))
+    }
+
+    pub fn is_infinite(&self) -> bool {
+        matches!(*self, Bound::NegInfinity | Bound::PosInfinity)
+    }
+
+    // The finite part of the bound, ignoring any epsilon term.
+    pub fn value(&self) -> Option<&Ast<'ctx>> {
+        match *self {
+            Bound::Finite(ref v) | Bound::Epsilon(ref v, _) => Some(v),
+            _ => None
+        }
+    }
+}
+
+impl<'ctx> SoftConstraint<'ctx> {
+    pub fn ast(&self) -> &Ast<'ctx> {
+        &self.ast
+    }
+
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub fn is_satisfied(&self, model: &Model<'ctx>) -> bool {
+        match model.eval(&self.ast) {
+            Some(v) => v.as_bool() == Some(true),
+            None => false
+        }
+    }
 }
 
 impl<'ctx> Drop for Optimize<'ctx> {
     fn drop(&mut self) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_optimize_dec_ref(self.ctx.z3_ctx, self.z3_opt);
         }
     }
@@ -0,0 +1,145 @@
+use z3_sys::*;
+use Context;
+use Params;
+use Symbol;
+use ParamKind;
+use ParamDescr;
+use ParamValue;
+use ffi::Z3_param_descrs_get_documentation;
+use z3_string;
+use std::fmt;
+
+impl<'ctx> Params<'ctx> {
+    pub fn new(ctx: &'ctx Context) -> Params<'ctx> {
+        Params {
+            ctx,
+            z3_params: unsafe {
+                let p = Z3_mk_params(ctx.z3_ctx);
+                Z3_params_inc_ref(ctx.z3_ctx, p);
+                p
+            },
+            values: Vec::new()
+        }
+    }
+
+    // The last value set for a parameter, if it was set as a u32.
+    pub(crate) fn get_u32(&self, k: &str) -> Option<u32> {
+        match self.values.iter().rev().find(|(n, _)| n == k) {
+            Some((_, ParamValue::U32(u))) => Some(*u),
+            _ => None
+        }
+    }
+
+    pub fn set_bool(&mut self, k: &str, v: bool) {
+        let ks = Symbol::from_string(self.ctx, k);
+        unsafe {
+            Z3_params_set_bool(self.ctx.z3_ctx, self.z3_params, ks.z3_sym,
+                               if v { Z3_TRUE } else { Z3_FALSE });
+        }
+        self.values.push((k.to_string(), ParamValue::Bool(v)));
+    }
+
+    pub fn set_u32(&mut self, k: &str, v: u32) {
+        let ks = Symbol::from_string(self.ctx, k);
+        unsafe {
+            Z3_params_set_uint(self.ctx.z3_ctx, self.z3_params, ks.z3_sym,
+                               v as ::libc::c_uint);
+        }
+        self.values.push((k.to_string(), ParamValue::U32(v)));
+    }
+
+    pub fn set_f64(&mut self, k: &str, v: f64) {
+        let ks = Symbol::from_string(self.ctx, k);
+        unsafe {
+            Z3_params_set_double(self.ctx.z3_ctx, self.z3_params, ks.z3_sym,
+                                 v as ::libc::c_double);
+        }
+        self.values.push((k.to_string(), ParamValue::F64(v)));
+    }
+
+    pub(crate) fn set_value(&mut self, k: &str, v: &ParamValue) {
+        match v {
+            ParamValue::Bool(b) => self.set_bool(k, *b),
+            ParamValue::U32(u) => self.set_u32(k, *u),
+            ParamValue::F64(f) => self.set_f64(k, *f),
+            ParamValue::Symbol(s) => self.set_symbol(k, s)
+        }
+    }
+
+    pub fn set_symbol(&mut self, k: &str, v: &str) {
+        let ks = Symbol::from_string(self.ctx, k);
+        let vs = Symbol::from_string(self.ctx, v);
+        unsafe {
+            Z3_params_set_symbol(self.ctx.z3_ctx, self.z3_params, ks.z3_sym,
+                                 vs.z3_sym);
+        }
+        self.values.push((k.to_string(), ParamValue::Symbol(v.to_string())));
+    }
+}
+
+impl ParamKind {
+    fn from_z3(k: Z3_param_kind) -> ParamKind {
+        match k {
+            Z3_PK_UINT => ParamKind::U32,
+            Z3_PK_BOOL => ParamKind::Bool,
+            Z3_PK_DOUBLE => ParamKind::F64,
+            Z3_PK_SYMBOL => ParamKind::Symbol,
+            Z3_PK_STRING => ParamKind::String,
+            _ => ParamKind::Other
+        }
+    }
+}
+
+impl ParamDescr {
+    // Reads out and releases a Z3_param_descrs.
+    pub(crate) fn from_z3(ctx: &Context, p: Z3_param_descrs) -> Vec<ParamDescr> {
+        unsafe {
+            Z3_param_descrs_inc_ref(ctx.z3_ctx, p);
+            let res = (0..Z3_param_descrs_size(ctx.z3_ctx, p)).map(|i| {
+                // Both strings live in the same buffer inside Z3.
+                let sym = Z3_param_descrs_get_name(ctx.z3_ctx, p, i);
+                let name = z3_string(Z3_get_symbol_string(ctx.z3_ctx, sym));
+                let doc = z3_string(
+                    Z3_param_descrs_get_documentation(ctx.z3_ctx, p, sym));
+                ParamDescr {
+                    name,
+                    kind: ParamKind::from_z3(
+                        Z3_param_descrs_get_kind(ctx.z3_ctx, p, sym)),
+                    doc
+                }
+            }).collect();
+            Z3_param_descrs_dec_ref(ctx.z3_ctx, p);
+            res
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> ParamKind {
+        self.kind
+    }
+
+    pub fn doc(&self) -> &str {
+        &self.doc
+    }
+}
+
+impl<'ctx> fmt::Display for Params<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = unsafe {
+            let p = Z3_params_to_string(self.ctx.z3_ctx, self.z3_params);
+            z3_string(p)
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl<'ctx> Drop for Params<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            Z3_params_dec_ref(self.ctx.z3_ctx, self.z3_params);
+        }
+    }
+}
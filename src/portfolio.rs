@@ -0,0 +1,317 @@
+use z3_sys::*;
+use ffi::Z3_model_translate;
+use Config;
+use Context;
+use Solver;
+use Model;
+use Ast;
+use Params;
+use SatResult;
+use Limits;
+use CancellationToken;
+use ParamValue;
+use Strategy;
+use Portfolio;
+use PortfolioResult;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A worker's context, with the assertions it is to check already
+// translated into it. The raw terms hold references in that context and
+// travel with it; nothing else refers to it.
+pub(crate) struct Job {
+    ctx: Context,
+    asts: Vec<Z3_ast>
+}
+
+unsafe impl Send for Job {}
+
+// What a worker hands back: its context, and a reference in it to the
+// model it found, if any.
+pub(crate) struct Done {
+    ctx: Context,
+    model: Option<Z3_model>
+}
+
+unsafe impl Send for Done {}
+
+impl Strategy {
+    pub fn new(name: &str) -> Strategy {
+        Strategy {
+            name: name.to_string(),
+            logic: None,
+            tactic: None,
+            seed: None,
+            params: Vec::new()
+        }
+    }
+
+    pub fn logic(mut self, logic: &str) -> Strategy {
+        self.logic = Some(logic.to_string());
+        self
+    }
+
+    // Takes precedence over logic when both are given.
+    pub fn tactic(mut self, tactic: &str) -> Strategy {
+        self.tactic = Some(tactic.to_string());
+        self
+    }
+
+    pub fn seed(mut self, seed: u32) -> Strategy {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn param(mut self, k: &str, v: ParamValue) -> Strategy {
+        self.params.push((k.to_string(), v));
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn solver<'ctx>(&self, ctx: &'ctx Context)
+                    -> Result<Solver<'ctx>, String> {
+        let solver = match (&self.tactic, &self.logic) {
+            (Some(t), _) => Solver::new_from_tactic(ctx, t)
+                .ok_or_else(|| format!("unknown tactic {}", t))?,
+            (None, Some(l)) => Solver::new_for_logic(ctx, l),
+            (None, None) => Solver::new(ctx)
+        };
+        let mut params = Params::new(ctx);
+        if let Some(seed) = self.seed {
+            params.set_u32("random_seed", seed);
+        }
+        for (k, v) in &self.params {
+            params.set_value(k, v);
+        }
+        match ctx.catching(|| solver.set_params(&params)) {
+            (_, Some(e)) => Err(e),
+            (_, None) => Ok(solver)
+        }
+    }
+
+    // Runs on a worker thread, in the job's context. The model is kept
+    // only for a sat answer that won: in a canceled race there is already
+    // a winner.
+    pub(crate) fn solve(&self, job: Job, limits: &Limits,
+                        token: &CancellationToken) -> (SatResult, Done) {
+        let Job { ctx, asts } = job;
+        token.register(&ctx);
+        let (res, model) = match self.solver(&ctx) {
+            Ok(solver) => {
+                for a in &asts {
+                    solver.assert(&Ast::new(&ctx, *a));
+                }
+                let res = solver.check_with_limits(limits);
+                debug!("strategy {} finished: {:?}", self.name, res);
+                let model = if res == SatResult::Sat && !token.is_canceled() {
+                    solver.get_model().map(|m| {
+                        unsafe { Z3_model_inc_ref(ctx.z3_ctx, m.z3_mdl); }
+                        m.z3_mdl
+                    })
+                } else {
+                    None
+                };
+                (res, model)
+            }
+            Err(e) => {
+                warn!("strategy {}: {}", self.name, e);
+                (SatResult::Unknown, None)
+            }
+        };
+        for a in asts {
+            unsafe { Z3_dec_ref(ctx.z3_ctx, a); }
+        }
+        (res, Done { ctx, model })
+    }
+}
+
+impl Strategy {
+    // Runs on a worker thread, in the job's context: checks its
+    // assertions together with each set of extra ones that `next` comes
+    // up with, reporting each answer, until `next` has no more, `report`
+    // declines to go on or the token is canceled. Each set is pushed and
+    // popped, so the context holds one at a time.
+    pub(crate) fn solve_each<N, R>(&self, job: Job, limits: &Limits,
+                                   token: &CancellationToken,
+                                   mut next: N, mut report: R)
+        where N: for<'c> FnMut(&'c Context) -> Option<(usize, Vec<Ast<'c>>)>,
+              R: FnMut(usize, SatResult) -> bool
+    {
+        let Job { ctx, asts } = job;
+        token.register(&ctx);
+        match self.solver(&ctx) {
+            Ok(solver) => {
+                for a in &asts {
+                    solver.assert(&Ast::new(&ctx, *a));
+                }
+                while !token.is_canceled() {
+                    let (i, extra) = match next(&ctx) {
+                        Some(n) => n,
+                        None => break
+                    };
+                    solver.push();
+                    for a in &extra {
+                        solver.assert(a);
+                    }
+                    let res = solver.check_with_limits(limits);
+                    solver.pop(1);
+                    if !report(i, res) {
+                        break;
+                    }
+                }
+            }
+            Err(e) => warn!("strategy {}: {}", self.name, e)
+        }
+        for a in asts {
+            unsafe { Z3_dec_ref(ctx.z3_ctx, a); }
+        }
+    }
+}
+
+impl Job {
+    // Translates the assertions into a fresh context, on the thread that
+    // owns theirs.
+    pub(crate) fn new(assertions: &[Ast]) -> Job {
+        let ctx = Context::new(&Config::new());
+        let asts = assertions.iter().map(|a| {
+            let t = a.translate(&ctx);
+            unsafe { Z3_inc_ref(ctx.z3_ctx, t.z3_ast); }
+            t.z3_ast
+        }).collect();
+        Job { ctx, asts }
+    }
+}
+
+impl Done {
+    // Call only once the worker has finished with the context.
+    fn model<'dest>(&self, dest: &'dest Context) -> Option<Model<'dest>> {
+        self.model.map(|m| unsafe {
+            let t = Z3_model_translate(self.ctx.z3_ctx, m, dest.z3_ctx);
+            Z3_model_inc_ref(dest.z3_ctx, t);
+            Model { ctx: dest, z3_mdl: t }
+        })
+    }
+}
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        if let Some(m) = self.model {
+            unsafe { Z3_model_dec_ref(self.ctx.z3_ctx, m); }
+        }
+    }
+}
+
+impl<'ctx> Portfolio<'ctx> {
+    // Snapshots the solver's current assertions; later changes to the
+    // solver do not reach the portfolio.
+    pub fn new(solver: &Solver<'ctx>) -> Portfolio<'ctx> {
+        Portfolio {
+            ctx: solver.ctx,
+            assertions: solver.get_assertions(),
+            strategies: Vec::new(),
+            limits: Limits::new()
+        }
+    }
+
+    pub fn from_string(ctx: &'ctx Context, smt2: &str)
+                       -> Result<Portfolio<'ctx>, String> {
+        let solver = Solver::new(ctx);
+        solver.from_string(smt2)?;
+        Ok(Portfolio::new(&solver))
+    }
+
+    pub fn strategy(mut self, strategy: Strategy) -> Portfolio<'ctx> {
+        self.strategies.push(strategy);
+        self
+    }
+
+    // Applied to every strategy's check.
+    pub fn limits(mut self, limits: Limits) -> Portfolio<'ctx> {
+        self.limits = limits;
+        self
+    }
+
+    pub fn strategies(&self) -> &[Strategy] {
+        &self.strategies
+    }
+
+    // Starts every strategy at once and returns with the first sat or unsat;
+    // the others are interrupted and joined before this returns. Unknown
+    // only if every strategy gave up. The assertions are translated into
+    // the workers' contexts here, before any of them starts, and the
+    // winning model back once all have stopped.
+    pub fn run(&self) -> PortfolioResult<'ctx> {
+        let start = Instant::now();
+        let token = CancellationToken::new();
+        let (tx, rx) = mpsc::channel();
+        let workers: Vec<_> = self.strategies.iter().cloned().enumerate()
+            .map(|(i, strategy)| {
+                let tx = tx.clone();
+                let token = token.clone();
+                let job = Job::new(&self.assertions);
+                let limits = self.limits;
+                thread::spawn(move || {
+                    let (res, done) = strategy.solve(job, &limits, &token);
+                    let _ = tx.send((i, res));
+                    done
+                })
+            }).collect();
+        drop(tx);
+
+        let mut outcome = PortfolioResult {
+            result: SatResult::Unknown,
+            winner: None,
+            name: None,
+            model: None,
+            elapsed: Duration::from_secs(0)
+        };
+        for (i, res) in rx.iter() {
+            if res != SatResult::Unknown {
+                outcome.result = res;
+                outcome.winner = Some(i);
+                outcome.name = Some(self.strategies[i].name.clone());
+                break;
+            }
+        }
+        outcome.elapsed = start.elapsed();
+        token.cancel();
+        for (i, w) in workers.into_iter().enumerate() {
+            if let Ok(done) = w.join() {
+                if outcome.winner == Some(i) {
+                    outcome.model = done.model(self.ctx);
+                }
+            }
+        }
+        outcome
+    }
+}
+
+impl<'ctx> PortfolioResult<'ctx> {
+    pub fn result(&self) -> SatResult {
+        self.result
+    }
+
+    // Index into Portfolio::strategies of the strategy that answered.
+    pub fn winner(&self) -> Option<usize> {
+        self.winner
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    // The winning model, in the context of the portfolio's assertions,
+    // when the answer was sat.
+    pub fn model(&self) -> Option<&Model<'ctx>> {
+        self.model.as_ref()
+    }
+
+    // Time until the first definite answer, or until all strategies gave up.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
@@ -0,0 +1,207 @@
+use z3_sys::*;
+use Ast;
+use ProofRule;
+use ProofNode;
+use Capability;
+use version;
+use z3_string;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+
+// Proof rules in Z3_decl_kind order as of 4.8.12, starting at 0x500; see
+// Capability::ProofRuleKinds. z3-sys has its own Z3_OP_PR_* constants,
+// but they predate several additions and no longer line up.
+const PR_BASE: u32 = 0x500;
+const RULES: [ProofRule; 42] = [
+    ProofRule::Undef,
+    ProofRule::True,
+    ProofRule::Asserted,
+    ProofRule::Goal,
+    ProofRule::ModusPonens,
+    ProofRule::Reflexivity,
+    ProofRule::Symmetry,
+    ProofRule::Transitivity,
+    ProofRule::TransitivityStar,
+    ProofRule::Monotonicity,
+    ProofRule::QuantIntro,
+    ProofRule::Bind,
+    ProofRule::Distributivity,
+    ProofRule::AndElim,
+    ProofRule::NotOrElim,
+    ProofRule::Rewrite,
+    ProofRule::RewriteStar,
+    ProofRule::PullQuant,
+    ProofRule::PushQuant,
+    ProofRule::ElimUnusedVars,
+    ProofRule::Der,
+    ProofRule::QuantInst,
+    ProofRule::Hypothesis,
+    ProofRule::Lemma,
+    ProofRule::UnitResolution,
+    ProofRule::IffTrue,
+    ProofRule::IffFalse,
+    ProofRule::Commutativity,
+    ProofRule::DefAxiom,
+    ProofRule::AssumptionAdd,
+    ProofRule::LemmaAdd,
+    ProofRule::RedundantDel,
+    ProofRule::ClauseTrail,
+    ProofRule::DefIntro,
+    ProofRule::ApplyDef,
+    ProofRule::IffOeq,
+    ProofRule::NnfPos,
+    ProofRule::NnfNeg,
+    ProofRule::Skolemize,
+    ProofRule::ModusPonensOeq,
+    ProofRule::ThLemma,
+    ProofRule::HyperResolve
+];
+
+impl ProofRule {
+    fn from_z3(k: Z3_decl_kind, known: bool) -> ProofRule {
+        if known && k >= PR_BASE && ((k - PR_BASE) as usize) < RULES.len() {
+            RULES[(k - PR_BASE) as usize]
+        } else {
+            ProofRule::Other(k)
+        }
+    }
+}
+
+impl<'ctx> ProofNode<'ctx> {
+    // Unfolds a proof term, as returned by Solver::get_proof. Each proof
+    // step is an application whose last argument is the conclusion and
+    // whose other arguments are the proofs of its premises. Proofs can be
+    // far deeper than the call stack, so this keeps a stack of its own:
+    // a step goes on it once to have its premises pushed, and again to be
+    // built once they are.
+    pub fn from_proof(proof: &Ast<'ctx>) -> Rc<ProofNode<'ctx>> {
+        let ctx = proof.ctx;
+        let c = ctx.z3_ctx;
+        let known = version().supports(Capability::ProofRuleKinds);
+        let mut done: HashMap<usize, Rc<ProofNode<'ctx>>> = HashMap::new();
+        let mut stack = vec![(proof.z3_ast, false)];
+        while let Some((p, expanded)) = stack.pop() {
+            if done.contains_key(&(p as usize)) {
+                continue;
+            }
+            unsafe {
+                let app = Z3_to_app(c, p);
+                let n = Z3_get_app_num_args(c, app);
+                assert!(n > 0);
+                if !expanded {
+                    stack.push((p, true));
+                    for i in (0..n - 1).rev() {
+                        stack.push((Z3_get_app_arg(c, app, i), false));
+                    }
+                    continue;
+                }
+                let decl = Z3_get_app_decl(c, app);
+                let premises = (0..n - 1).map(|i| {
+                    done[&(Z3_get_app_arg(c, app, i) as usize)].clone()
+                }).collect();
+                let name = z3_string(Z3_get_symbol_string(c, Z3_get_decl_name(c, decl)));
+                done.insert(p as usize, Rc::new(ProofNode {
+                    rule: ProofRule::from_z3(Z3_get_decl_kind(c, decl), known),
+                    name,
+                    premises,
+                    conclusion: Ast::new(ctx, Z3_get_app_arg(c, app, n - 1)),
+                    proof: Ast::new(ctx, p)
+                }));
+            }
+        }
+        done[&(proof.z3_ast as usize)].clone()
+    }
+
+    pub fn rule(&self) -> ProofRule {
+        self.rule
+    }
+
+    // Z3's own name for the rule, such as "mp" or "unit-resolution".
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn premises(&self) -> &[Rc<ProofNode<'ctx>>] {
+        &self.premises
+    }
+
+    pub fn conclusion(&self) -> &Ast<'ctx> {
+        &self.conclusion
+    }
+
+    // The proof term this node was read from.
+    pub fn proof(&self) -> &Ast<'ctx> {
+        &self.proof
+    }
+
+    // Every distinct step of the proof, each before its premises.
+    pub fn nodes(&self) -> Vec<&ProofNode<'ctx>> {
+        let mut seen = HashSet::new();
+        let mut res = Vec::new();
+        let mut stack = vec![self];
+        while let Some(n) = stack.pop() {
+            if seen.insert(n as *const ProofNode) {
+                res.push(n);
+                stack.extend(n.premises.iter().rev().map(|p| &**p));
+            }
+        }
+        res
+    }
+
+    // The proof as a Graphviz digraph, with edges from each step to its
+    // premises.
+    pub fn to_dot(&self) -> String {
+        let nodes = self.nodes();
+        let ids: HashMap<*const ProofNode, usize> = nodes.iter().enumerate()
+            .map(|(i, n)| (*n as *const ProofNode, i)).collect();
+        let mut s = String::from("digraph proof {\n");
+        for (i, n) in nodes.iter().enumerate() {
+            s.push_str(&format!("  n{} [label=\"{}\\n{}\"];\n", i, n.name,
+                                n.conclusion.to_string().replace('\\', "\\\\")
+                                .replace('"', "\\\"").replace('\n', "\\n")));
+            for p in &n.premises {
+                s.push_str(&format!("  n{} -> n{};\n", i,
+                                    ids[&(&**p as *const ProofNode)]));
+            }
+        }
+        s.push_str("}\n");
+        s
+    }
+}
+
+// One step per line, premises indented under the step that uses them;
+// steps shared by several parents are printed in full only once.
+impl<'ctx> fmt::Display for ProofNode<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut ids = HashMap::new();
+        let mut stack = vec![(self, 0)];
+        while let Some((n, depth)) = stack.pop() {
+            let key = n as *const ProofNode;
+            if let Some(id) = ids.get(&key) {
+                writeln!(f, "{:2$}#{} (see above)", "", id, depth * 2)?;
+                continue;
+            }
+            let id = ids.len();
+            ids.insert(key, id);
+            writeln!(f, "{:4$}#{} {}: {}", "", id, n.name, n.conclusion,
+                     depth * 2)?;
+            stack.extend(n.premises.iter().rev().map(|p| (&**p, depth + 1)));
+        }
+        Ok(())
+    }
+}
+
+// Dropping a long chain of steps one inside the other would recurse as
+// deep as the proof; steps no one else holds are taken apart here
+// instead.
+impl<'ctx> Drop for ProofNode<'ctx> {
+    fn drop(&mut self) {
+        let mut stack: Vec<Rc<ProofNode<'ctx>>> = self.premises.drain(..).collect();
+        while let Some(p) = stack.pop() {
+            if let Ok(mut n) = Rc::try_unwrap(p) {
+                stack.append(&mut n.premises);
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use z3_sys::*;
+use SatResult;
+use ReasonUnknown;
+
+impl SatResult {
+    pub(crate) fn from_z3(l: Z3_lbool) -> SatResult {
+        match l {
+            Z3_L_TRUE => SatResult::Sat,
+            Z3_L_FALSE => SatResult::Unsat,
+            _ => SatResult::Unknown
+        }
+    }
+
+    pub fn is_sat(&self) -> bool {
+        *self == SatResult::Sat
+    }
+
+    pub fn is_unsat(&self) -> bool {
+        *self == SatResult::Unsat
+    }
+
+    pub fn is_unknown(&self) -> bool {
+        *self == SatResult::Unknown
+    }
+}
+
+impl ReasonUnknown {
+    pub(crate) fn from_z3_string(s: &str) -> ReasonUnknown {
+        match s {
+            "canceled" | "interrupted" => ReasonUnknown::Canceled,
+            "timeout" => ReasonUnknown::Timeout,
+            "max. resource limit exceeded" => ReasonUnknown::ResourceLimit,
+            "max. memory exceeded" | "memout" => ReasonUnknown::MemoryLimit,
+            _ => ReasonUnknown::Other(s.to_string())
+        }
+    }
+}
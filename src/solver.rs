@@ -3,48 +3,461 @@ use Context;
 use Solver;
 use Model;
 use Ast;
-use Z3_MUTEX;
+use SatResult;
+use ReasonUnknown;
+use Params;
+use Limits;
+use TimeBudget;
+use Symbol;
+use Consequence;
+use Models;
+use ffi::{Z3_stats_is_uint, Z3_solver_from_string, Z3_solver_get_consequences};
+use std::cell::{Cell, RefCell};
+use std::ffi::{CStr, CString};
+use z3_string;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// Puts back the limits a solver had before check_with_limits, for those
+// it changed, even if the check panics.
+struct RestoreLimits<'a, 'ctx: 'a> {
+    solver: &'a Solver<'ctx>,
+    prev: Limits,
+    changed: Limits
+}
+
+impl<'a, 'ctx> Drop for RestoreLimits<'a, 'ctx> {
+    fn drop(&mut self) {
+        let params = self.prev.restore_params(self.solver.ctx, &self.changed);
+        self.solver.set_params(&params);
+    }
+}
 
 impl<'ctx> Solver<'ctx> {
-    pub fn new(ctx: &Context) -> Solver {
-        Solver {
-            ctx: ctx,
+    pub fn new(ctx: &'ctx Context) -> Solver<'ctx> {
+        Solver::with_config_params(Solver {
+            ctx,
             z3_slv: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 let s = Z3_mk_solver(ctx.z3_ctx);
                 Z3_solver_inc_ref(ctx.z3_ctx, s);
                 s
+            },
+            limits: Cell::new(ctx.limits),
+            reason: RefCell::new(None)
+        })
+    }
+
+    pub fn new_for_logic(ctx: &'ctx Context, logic: &str) -> Solver<'ctx> {
+        let sym = Symbol::from_string(ctx, logic);
+        Solver::with_config_params(Solver {
+            ctx,
+            z3_slv: unsafe {
+                let s = Z3_mk_solver_for_logic(ctx.z3_ctx, sym.z3_sym);
+                Z3_solver_inc_ref(ctx.z3_ctx, s);
+                s
+            },
+            limits: Cell::new(ctx.limits),
+            reason: RefCell::new(None)
+        })
+    }
+
+    // None if Z3 knows no tactic by that name.
+    pub fn new_from_tactic(ctx: &'ctx Context, tactic: &str) -> Option<Solver<'ctx>> {
+        let known = unsafe {
+            (0..Z3_get_num_tactics(ctx.z3_ctx)).any(|i| {
+                let n = CStr::from_ptr(Z3_get_tactic_name(ctx.z3_ctx, i));
+                n.to_bytes() == tactic.as_bytes()
+            })
+        };
+        if !known {
+            return None;
+        }
+        let ts = CString::new(tactic).unwrap();
+        Some(Solver::with_config_params(Solver {
+            ctx,
+            z3_slv: unsafe {
+                let t = Z3_mk_tactic(ctx.z3_ctx, ts.as_ptr());
+                Z3_tactic_inc_ref(ctx.z3_ctx, t);
+                let s = Z3_mk_solver_from_tactic(ctx.z3_ctx, t);
+                Z3_solver_inc_ref(ctx.z3_ctx, s);
+                Z3_tactic_dec_ref(ctx.z3_ctx, t);
+                s
+            },
+            limits: Cell::new(ctx.limits),
+            reason: RefCell::new(None)
+        }))
+    }
+
+    // Sets what the context's config has for solvers: seeds and threads,
+    // which Z3 only takes as solver parameters.
+    fn with_config_params(self) -> Solver<'ctx> {
+        if !self.ctx.solver_params.is_empty() {
+            let mut params = Params::new(self.ctx);
+            for (k, v) in &self.ctx.solver_params {
+                params.set_value(k, v);
             }
+            self.set_params(&params);
+        }
+        self
+    }
+
+    // Adds the assertions of an SMT-LIB2 script, declaring what it declares.
+    pub fn from_string(&self, smt2: &str) -> Result<(), String> {
+        let s = CString::new(smt2).unwrap();
+        match self.ctx.catching(|| unsafe {
+            Z3_solver_from_string(self.ctx.z3_ctx, self.z3_slv, s.as_ptr());
+        }) {
+            (_, Some(e)) => Err(e),
+            (_, None) => Ok(())
+        }
+    }
+
+    // A copy of this solver and its assertions in another context.
+    pub fn translate<'dest>(&self, dest: &'dest Context) -> Solver<'dest> {
+        Solver {
+            ctx: dest,
+            z3_slv: unsafe {
+                let s = Z3_solver_translate(self.ctx.z3_ctx,
+                                            self.z3_slv,
+                                            dest.z3_ctx);
+                Z3_solver_inc_ref(dest.z3_ctx, s);
+                s
+            },
+            limits: Cell::new(self.limits.get()),
+            reason: RefCell::new(None)
         }
     }
 
     pub fn assert(&self, ast: &Ast<'ctx>) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_solver_assert(self.ctx.z3_ctx,
                              self.z3_slv,
                              ast.z3_ast);
         }
     }
 
-    pub fn check(&self) -> bool {
+    pub fn get_assertions(&self) -> Vec<Ast<'ctx>> {
+        Ast::from_z3_ast_vector(self.ctx, unsafe {
+            Z3_solver_get_assertions(self.ctx.z3_ctx, self.z3_slv)
+        })
+    }
+
+    pub fn push(&self) {
+        unsafe {
+            Z3_solver_push(self.ctx.z3_ctx, self.z3_slv);
+        }
+    }
+
+    pub fn pop(&self, n: u32) {
+        unsafe {
+            Z3_solver_pop(self.ctx.z3_ctx, self.z3_slv, n as ::libc::c_uint);
+        }
+    }
+
+    // Models that differ on at least one of the terms, found by blocking
+    // each one in a scope of its own. The solver is back as it was once
+    // the iterator is dropped. With no terms there is at most one model.
+    pub fn models<'a>(&'a self, terms: &[&Ast<'ctx>]) -> Models<'a, 'ctx> {
+        self.push();
+        Models {
+            solver: self,
+            terms: terms.iter().map(|t| (*t).clone()).collect(),
+            limit: None,
+            found: 0,
+            done: false,
+            complete: false
+        }
+    }
+
+    pub fn check(&self) -> SatResult {
+        let (res, canceled) =
+            self.ctx.interrupt.run_check(Some(self.z3_slv), || unsafe {
+                Z3_solver_check(self.ctx.z3_ctx, self.z3_slv)
+            });
+        *self.reason.borrow_mut() =
+            if canceled { Some(ReasonUnknown::Canceled) } else { None };
+        res
+    }
+
+    // Finds which of the variables the assertions and assumptions fix to
+    // a single value. Consequences are only meaningful when this is Sat.
+    pub fn consequences(&self, assumptions: &[&Ast<'ctx>],
+                        variables: &[&Ast<'ctx>]) -> (SatResult, Vec<Consequence<'ctx>>) {
+        let ctx = self.ctx;
+        let asms = Ast::to_z3_ast_vector(ctx, assumptions);
+        let vars = Ast::to_z3_ast_vector(ctx, variables);
+        let out = Ast::to_z3_ast_vector(ctx, &[]);
+        let (res, canceled) =
+            ctx.interrupt.run_check(Some(self.z3_slv), || unsafe {
+                Z3_solver_get_consequences(ctx.z3_ctx, self.z3_slv,
+                                           asms, vars, out)
+            });
+        *self.reason.borrow_mut() =
+            if canceled { Some(ReasonUnknown::Canceled) } else { None };
+        let found = Ast::from_z3_ast_vector(ctx, out);
+        unsafe {
+            Z3_ast_vector_dec_ref(ctx.z3_ctx, asms);
+            Z3_ast_vector_dec_ref(ctx.z3_ctx, vars);
+            Z3_ast_vector_dec_ref(ctx.z3_ctx, out);
+        }
+        (res, found.into_iter().filter_map(|c| {
+            let cons = Consequence::from_ast(c.clone(), assumptions, variables);
+            if cons.is_none() {
+                warn!("consequence of unexpected shape: {}", c);
+            }
+            cons
+        }).collect())
+    }
+
+    // The variables that take the same value in every model, with that
+    // value; None unless the assertions are satisfiable.
+    pub fn backbone(&self, variables: &[&Ast<'ctx>]) -> Option<Vec<(Ast<'ctx>, Ast<'ctx>)>> {
+        match self.consequences(&[], variables) {
+            (SatResult::Sat, found) => Some(found.into_iter().map(|c| {
+                (c.variable, c.value)
+            }).collect()),
+            _ => None
+        }
+    }
+
+    // Runs a check under the given limits. The solver's own limits, from
+    // its Config or set_params, are back in force afterwards. Running out
+    // of any of them comes back as Unknown, with a reason naming the
+    // limit.
+    pub fn check_with_limits(&self, limits: &Limits) -> SatResult {
+        let _restore = RestoreLimits {
+            solver: self,
+            prev: self.limits.get(),
+            changed: *limits
+        };
+        self.set_params(&limits.params(self.ctx));
+
+        let rlimit_before = self.get_statistic("rlimit count").unwrap_or(0.0);
+        let start = Instant::now();
+        let res = self.check();
+        let elapsed = start.elapsed();
+
+        if res == SatResult::Unknown && self.reason.borrow().is_none() {
+            let rlimit_used =
+                self.get_statistic("rlimit count").unwrap_or(0.0) - rlimit_before;
+            let reported = self.get_reason_unknown();
+            *self.reason.borrow_mut() =
+                limits.exhausted(reported, elapsed, rlimit_used);
+        }
+        res
+    }
+
+    pub fn check_with_timeout(&self, timeout: Duration) -> SatResult {
+        self.check_with_limits(&Limits::new().timeout(timeout))
+    }
+
+    // Checks with the next share of the budget, or fails straight away
+    // with ReasonUnknown::Timeout if the budget is already spent.
+    pub fn check_with_budget(&self, budget: &mut TimeBudget) -> SatResult {
+        match budget.next_timeout() {
+            Some(t) => self.check_with_timeout(t),
+            None => {
+                *self.reason.borrow_mut() = Some(ReasonUnknown::Timeout);
+                SatResult::Unknown
+            }
+        }
+    }
+
+    pub fn set_params(&self, params: &Params<'ctx>) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
-            Z3_solver_check(self.ctx.z3_ctx,
-                            self.z3_slv) == Z3_TRUE
+            Z3_solver_set_params(self.ctx.z3_ctx,
+                                 self.z3_slv,
+                                 params.z3_params);
         }
+        let mut limits = self.limits.get();
+        limits.update(params);
+        self.limits.set(limits);
     }
 
-    pub fn get_model(&self) -> Model<'ctx> {
+    // Looks up a solver statistic such as "rlimit count" or "max memory";
+    // Z3 prints these with dashes, but the keys use spaces.
+    pub fn get_statistic(&self, key: &str) -> Option<f64> {
+        unsafe {
+            let st = Z3_solver_get_statistics(self.ctx.z3_ctx, self.z3_slv);
+            Z3_stats_inc_ref(self.ctx.z3_ctx, st);
+            let mut res = None;
+            for i in 0..Z3_stats_size(self.ctx.z3_ctx, st) {
+                let k = CStr::from_ptr(Z3_stats_get_key(self.ctx.z3_ctx, st, i));
+                if k.to_bytes() == key.as_bytes() {
+                    res = Some(if Z3_stats_is_uint(self.ctx.z3_ctx, st, i) {
+                        Z3_stats_get_uint_value(self.ctx.z3_ctx, st, i) as f64
+                    } else {
+                        Z3_stats_get_double_value(self.ctx.z3_ctx, st, i)
+                    });
+                    break;
+                }
+            }
+            Z3_stats_dec_ref(self.ctx.z3_ctx, st);
+            res
+        }
+    }
+
+    pub fn get_reason_unknown(&self) -> ReasonUnknown {
+        if let Some(ref r) = *self.reason.borrow() {
+            return r.clone();
+        }
+        ReasonUnknown::from_z3_string(&unsafe {
+            let p = Z3_solver_get_reason_unknown(self.ctx.z3_ctx,
+                                                 self.z3_slv);
+            z3_string(p)
+        })
+    }
+
+    // The proof of the last unsat answer; the context needs proof
+    // generation turned on in its Config.
+    pub fn get_proof(&self) -> Option<Ast<'ctx>> {
+        let (p, err) = self.ctx.catching(|| unsafe {
+            Z3_solver_get_proof(self.ctx.z3_ctx, self.z3_slv)
+        });
+        if let Some(e) = err {
+            debug!("no proof: {}", e);
+            return None;
+        }
+        if p.is_null() {
+            None
+        } else {
+            Some(Ast::new(self.ctx, p))
+        }
+    }
+
+    pub fn get_model(&self) -> Option<Model<'ctx>> {
         Model::of_solver(self)
     }
 }
 
+impl<'a, 'ctx> Models<'a, 'ctx> {
+    pub fn limit(mut self, n: usize) -> Models<'a, 'ctx> {
+        self.limit = Some(n);
+        self
+    }
+
+    // Whether every model has been seen, rather than the enumeration
+    // stopping at the limit or on an Unknown.
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+impl<'a, 'ctx> Iterator for Models<'a, 'ctx> {
+    type Item = Model<'ctx>;
+
+    fn next(&mut self) -> Option<Model<'ctx>> {
+        if self.done || matches!(self.limit, Some(n) if self.found >= n) {
+            return None;
+        }
+        match self.solver.check() {
+            SatResult::Sat => (),
+            res => {
+                self.done = true;
+                self.complete = res == SatResult::Unsat;
+                return None;
+            }
+        }
+        let model = match self.solver.get_model() {
+            Some(m) => m,
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+        self.found += 1;
+        let ctx = self.solver.ctx;
+        let diffs: Vec<Ast<'ctx>> = self.terms.iter().filter_map(|t| {
+            model.eval(t).map(|v| t._eq(&v).not())
+        }).collect();
+        let block = match diffs.split_first() {
+            Some((first, rest)) => first.or(&rest.iter().collect::<Vec<&Ast>>()),
+            None => ctx.from_bool(false)
+        };
+        self.solver.assert(&block);
+        Some(model)
+    }
+}
+
+impl<'a, 'ctx> Drop for Models<'a, 'ctx> {
+    fn drop(&mut self) {
+        self.solver.pop(1);
+    }
+}
+
+impl<'ctx> Consequence<'ctx> {
+    // Z3 hands back (=> (and a_1 ... a_n) fact), with a single assumption
+    // or true standing in for the conjunction, and the fact one of v,
+    // (not v) or (= v value). None for anything else.
+    fn from_ast(ast: Ast<'ctx>, passed: &[&Ast<'ctx>],
+                variables: &[&Ast<'ctx>]) -> Option<Consequence<'ctx>> {
+        let args = ast.app_args();
+        if args.len() != 2 || ast.app_name().as_deref() != Some("=>") {
+            return None;
+        }
+        let ctx = ast.ctx;
+        // A conjunction that was passed as one assumption is not split.
+        let assumptions = match args[0].app_name().as_deref() {
+            Some("true") => Vec::new(),
+            Some("and") if !passed.contains(&&args[0]) => args[0].app_args(),
+            _ => vec![args[0].clone()]
+        };
+        // Compared against the variables themselves, as a boolean variable
+        // may well be an equation or negation of its own.
+        let fact = &args[1];
+        let fact_args = fact.app_args();
+        let (variable, value) = if variables.contains(&fact) {
+            (fact.clone(), ctx.from_bool(true))
+        } else if fact_args.len() == 1 &&
+            fact.app_name().as_deref() == Some("not") &&
+            variables.iter().any(|v| **v == fact_args[0]) {
+            (fact_args[0].clone(), ctx.from_bool(false))
+        } else if fact_args.len() == 2 && fact.app_name().as_deref() == Some("=") {
+            let mut ab = fact_args.into_iter();
+            (ab.next()?, ab.next()?)
+        } else {
+            return None;
+        };
+        Some(Consequence {
+            assumptions,
+            variable,
+            value,
+            ast
+        })
+    }
+
+    pub fn assumptions(&self) -> &[Ast<'ctx>] {
+        &self.assumptions
+    }
+
+    pub fn variable(&self) -> &Ast<'ctx> {
+        &self.variable
+    }
+
+    pub fn value(&self) -> &Ast<'ctx> {
+        &self.value
+    }
+
+    // The implication as Z3 stated it.
+    pub fn ast(&self) -> &Ast<'ctx> {
+        &self.ast
+    }
+}
+
+// Prints the assertions as an SMT-LIB2 script that from_string reads back.
+impl<'ctx> fmt::Display for Solver<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = unsafe {
+            let p = Z3_solver_to_string(self.ctx.z3_ctx, self.z3_slv);
+            z3_string(p)
+        };
+        write!(f, "{}", s)
+    }
+}
 
 impl<'ctx> Drop for Solver<'ctx> {
     fn drop(&mut self) {
         unsafe {
-            let guard = Z3_MUTEX.lock().unwrap();
             Z3_solver_dec_ref(self.ctx.z3_ctx, self.z3_slv);
         }
     }
@@ -1,81 +1,194 @@
 use z3_sys::*;
+use ffi::{Z3_mk_array_sort_n, Z3_is_eq_sort};
 use Context;
 use Symbol;
 use Sort;
-use Z3_MUTEX;
+use SortKind;
+use Capability;
+use version;
+use z3_string;
+use z3_len;
+use std::hash::{Hash, Hasher};
+use std::fmt;
 
 impl<'ctx> Sort<'ctx> {
+    pub(crate) fn from_z3(ctx: &'ctx Context, s: Z3_sort) -> Sort<'ctx> {
+        assert!(!s.is_null());
+        unsafe {
+            Z3_inc_ref(ctx.z3_ctx, Z3_sort_to_ast(ctx.z3_ctx, s));
+        }
+        Sort { ctx, z3_sort: s }
+    }
 
     pub fn uninterpretd(ctx: &'ctx Context, sym: &Symbol<'ctx>) -> Sort<'ctx> {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_uninterpreted_sort(ctx.z3_ctx, sym.z3_sym)
-            }
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_uninterpreted_sort(ctx.z3_ctx, sym.z3_sym)
+        })
+    }
+
+    pub fn bool(ctx: &'ctx Context) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_bool_sort(ctx.z3_ctx)
+        })
+    }
+
+    pub fn int(ctx: &'ctx Context) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_int_sort(ctx.z3_ctx)
+        })
+    }
+
+    pub fn real(ctx: &'ctx Context) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_real_sort(ctx.z3_ctx)
+        })
+    }
+
+    pub fn bitvector(ctx: &'ctx Context, sz: u32) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_bv_sort(ctx.z3_ctx, sz as ::libc::c_uint)
+        })
+    }
+
+    pub fn array(ctx: &'ctx Context,
+                 domain: &Sort<'ctx>,
+                 range: &Sort<'ctx>) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_array_sort(ctx.z3_ctx, domain.z3_sort, range.z3_sort)
+        })
+    }
+
+    // Arrays indexed by tuples of the domain sorts.
+    pub fn array_n(ctx: &'ctx Context,
+                   domain: &[&Sort<'ctx>],
+                   range: &Sort<'ctx>) -> Result<Sort<'ctx>, String> {
+        version().require(Capability::ArrayN)?;
+        let tmp : Vec<Z3_sort> = domain.iter().map(|s| s.z3_sort).collect();
+        Ok(Sort::from_z3(ctx, unsafe {
+            Z3_mk_array_sort_n(ctx.z3_ctx, z3_len(&tmp),
+                                   tmp.as_ptr(), range.z3_sort)
+        }))
+    }
+
+    // A sort with exactly `size` elements, numbered from 0; what the
+    // Datalog engine of Fixedpoint works with best.
+    pub fn finite_domain(ctx: &'ctx Context, sym: &Symbol<'ctx>,
+                         size: u64) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_finite_domain_sort(ctx.z3_ctx, sym.z3_sym, size)
+        })
+    }
+
+    pub fn set(ctx: &'ctx Context, elt: &Sort<'ctx>) -> Sort<'ctx> {
+        Sort::from_z3(ctx, unsafe {
+            Z3_mk_set_sort(ctx.z3_ctx, elt.z3_sort)
+        })
+    }
+
+    pub fn kind(&self) -> SortKind {
+        SortKind::from_z3(unsafe {
+            Z3_get_sort_kind(self.ctx.z3_ctx, self.z3_sort)
+        })
+    }
+
+    pub fn name(&self) -> String {
+        unsafe {
+            let sym = Z3_get_sort_name(self.ctx.z3_ctx, self.z3_sort);
+            z3_string(Z3_get_symbol_string(self.ctx.z3_ctx, sym))
         }
     }
 
-    pub fn bool(ctx: &Context) -> Sort {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_bool_sort(ctx.z3_ctx)
-            }
+    // The width of a bit-vector sort; None for other sorts.
+    pub fn bv_size(&self) -> Option<u32> {
+        if self.kind() != SortKind::BitVector {
+            return None;
         }
+        Some(unsafe {
+            Z3_get_bv_sort_size(self.ctx.z3_ctx, self.z3_sort)
+        })
     }
 
-    pub fn int(ctx: &Context) -> Sort {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_int_sort(ctx.z3_ctx)
-            }
+    // The index and element sorts of an array (or set) sort.
+    pub fn array_domain(&self) -> Option<Sort<'ctx>> {
+        if self.kind() != SortKind::Array {
+            return None;
         }
+        Some(Sort::from_z3(self.ctx, unsafe {
+            Z3_get_array_sort_domain(self.ctx.z3_ctx, self.z3_sort)
+        }))
     }
 
-    pub fn real(ctx: &Context) -> Sort {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_real_sort(ctx.z3_ctx)
-            }
+    pub fn array_range(&self) -> Option<Sort<'ctx>> {
+        if self.kind() != SortKind::Array {
+            return None;
         }
+        Some(Sort::from_z3(self.ctx, unsafe {
+            Z3_get_array_sort_range(self.ctx.z3_ctx, self.z3_sort)
+        }))
     }
+}
 
-    pub fn bitvector(ctx: &Context, sz: u32) -> Sort {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_bv_sort(ctx.z3_ctx, sz as ::libc::c_uint)
-            }
+impl SortKind {
+    fn from_z3(k: Z3_sort_kind) -> SortKind {
+        match k {
+            Z3_UNINTERPRETED_SORT => SortKind::Uninterpreted,
+            Z3_BOOL_SORT => SortKind::Bool,
+            Z3_INT_SORT => SortKind::Int,
+            Z3_REAL_SORT => SortKind::Real,
+            Z3_BV_SORT => SortKind::BitVector,
+            Z3_ARRAY_SORT => SortKind::Array,
+            Z3_DATATYPE_SORT => SortKind::Datatype,
+            Z3_RELATION_SORT => SortKind::Relation,
+            Z3_FINITE_DOMAIN_SORT => SortKind::FiniteDomain,
+            Z3_FLOATING_POINT_SORT => SortKind::FloatingPoint,
+            Z3_ROUNDING_MODE_SORT => SortKind::RoundingMode,
+            // Newer than z3-sys.
+            11 => SortKind::Seq,
+            12 => SortKind::Re,
+            _ => SortKind::Unknown
         }
     }
+}
 
-    pub fn array(ctx: &'ctx Context,
-                 domain: &Sort<'ctx>,
-                 range: &Sort<'ctx>) -> Sort<'ctx> {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_array_sort(ctx.z3_ctx, domain.z3_sort, range.z3_sort)
-            }
+impl<'ctx> fmt::Display for Sort<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = z3_string(unsafe {
+            Z3_sort_to_string(self.ctx.z3_ctx, self.z3_sort)
+        });
+        write!(f, "{}", s)
+    }
+}
+
+impl<'ctx> Clone for Sort<'ctx> {
+    fn clone(&self) -> Sort<'ctx> {
+        Sort::from_z3(self.ctx, self.z3_sort)
+    }
+}
+
+impl<'ctx> Hash for Sort<'ctx> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        unsafe {
+            let u = Z3_get_ast_hash(self.ctx.z3_ctx,
+                                    Z3_sort_to_ast(self.ctx.z3_ctx, self.z3_sort));
+            u.hash(state);
         }
     }
+}
 
-    pub fn set(ctx: &'ctx Context, elt: &Sort<'ctx>) -> Sort<'ctx> {
-        Sort {
-            ctx: ctx,
-            z3_sort: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
-                Z3_mk_set_sort(ctx.z3_ctx, elt.z3_sort)
-            }
+impl<'ctx> PartialEq<Sort<'ctx>> for Sort<'ctx> {
+    fn eq(&self, other: &Sort<'ctx>) -> bool {
+        unsafe {
+            Z3_is_eq_sort(self.ctx.z3_ctx, self.z3_sort, other.z3_sort)
         }
     }
+}
+
+impl<'ctx> Eq for Sort<'ctx> { }
 
+impl<'ctx> Drop for Sort<'ctx> {
+    fn drop(&mut self) {
+        unsafe {
+            Z3_dec_ref(self.ctx.z3_ctx, Z3_sort_to_ast(self.ctx.z3_ctx, self.z3_sort));
+        }
+    }
 }
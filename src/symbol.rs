@@ -1,16 +1,19 @@
 use z3_sys::*;
 use Symbol;
+use SymbolKind;
+use SymbolName;
 use Context;
-use Z3_MUTEX;
+use z3_string;
 use std::ffi::CString;
+use std::hash::{Hash, Hasher};
+use std::fmt;
 
 impl<'ctx> Symbol<'ctx> {
-    pub fn from_int(ctx: &Context, i: u32) -> Symbol {
+    pub fn from_int(ctx: &'ctx Context, i: u32) -> Symbol<'ctx> {
         Symbol {
-            ctx: ctx,
+            ctx,
             cst: None,
             z3_sym: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_mk_int_symbol(ctx.z3_ctx, i as ::libc::c_int)
             }
         }
@@ -20,12 +23,95 @@ impl<'ctx> Symbol<'ctx> {
         let ss = CString::new(s).unwrap();
         let p = ss.as_ptr();
         Symbol {
-            ctx: ctx,
+            ctx,
             cst: Some(ss),
             z3_sym: unsafe {
-                let guard = Z3_MUTEX.lock().unwrap();
                 Z3_mk_string_symbol(ctx.z3_ctx, p)
             }
         }
     }
+
+    pub(crate) fn from_z3(ctx: &'ctx Context, sym: Z3_symbol) -> Symbol<'ctx> {
+        Symbol {
+            ctx,
+            cst: None,
+            z3_sym: sym
+        }
+    }
+
+    pub fn from_name(ctx: &'ctx Context, name: &SymbolName) -> Symbol<'ctx> {
+        match *name {
+            SymbolName::Int(i) => Symbol::from_int(ctx, i),
+            SymbolName::Str(ref s) => Symbol::from_string(ctx, s)
+        }
+    }
+
+    pub fn kind(&self) -> SymbolKind {
+        match unsafe { Z3_get_symbol_kind(self.ctx.z3_ctx, self.z3_sym) } {
+            Z3_INT_SYMBOL => SymbolKind::Int,
+            _ => SymbolKind::Str
+        }
+    }
+
+    pub fn as_int(&self) -> Option<u32> {
+        match self.kind() {
+            SymbolKind::Int => Some(unsafe {
+                Z3_get_symbol_int(self.ctx.z3_ctx, self.z3_sym) as u32
+            }),
+            SymbolKind::Str => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<String> {
+        match self.kind() {
+            SymbolKind::Int => None,
+            SymbolKind::Str => Some(z3_string(unsafe {
+                Z3_get_symbol_string(self.ctx.z3_ctx, self.z3_sym)
+            }))
+        }
+    }
+
+    pub fn name(&self) -> SymbolName {
+        match self.as_int() {
+            Some(i) => SymbolName::Int(i),
+            None => SymbolName::Str(self.as_str().unwrap())
+        }
+    }
+}
+
+impl<'a, 'ctx> From<&'a Symbol<'ctx>> for SymbolName {
+    fn from(sym: &'a Symbol<'ctx>) -> SymbolName {
+        sym.name()
+    }
+}
+
+// Integer symbols print the way Z3 prints them, as k!n.
+impl<'ctx> fmt::Display for Symbol<'ctx> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Display for SymbolName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SymbolName::Int(i) => write!(f, "k!{}", i),
+            SymbolName::Str(ref s) => write!(f, "{}", s)
+        }
+    }
+}
+
+// Z3 interns symbols, so equal names mean equal pointers.
+impl<'ctx> PartialEq<Symbol<'ctx>> for Symbol<'ctx> {
+    fn eq(&self, other: &Symbol<'ctx>) -> bool {
+        self.z3_sym == other.z3_sym
+    }
+}
+
+impl<'ctx> Eq for Symbol<'ctx> { }
+
+impl<'ctx> Hash for Symbol<'ctx> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.z3_sym as usize).hash(state);
+    }
 }
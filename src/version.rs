@@ -0,0 +1,123 @@
+use z3_sys::*;
+use ffi::{self, Z3_get_full_version};
+use Version;
+use Capability;
+use z3_string;
+use std::cmp::Ordering;
+use std::fmt;
+
+impl Version {
+    pub(crate) fn linked() -> Version {
+        let (mut major, mut minor, mut build, mut revision) = (0, 0, 0, 0);
+        unsafe {
+            Z3_get_version(&mut major, &mut minor, &mut build, &mut revision);
+        }
+        let full = if ffi::linked("Z3_get_full_version") {
+            z3_string(unsafe { Z3_get_full_version() })
+        } else {
+            format!("Z3 {}.{}.{}.{}", major, minor, build, revision)
+        };
+        Version { major, minor, build, revision, full }
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    pub fn build(&self) -> u32 {
+        self.build
+    }
+
+    pub fn revision(&self) -> u32 {
+        self.revision
+    }
+
+    // Z3's own description, with the git hash of builds that have one.
+    pub fn full(&self) -> &str {
+        &self.full
+    }
+
+    pub fn at_least(&self, major: u32, minor: u32, build: u32) -> bool {
+        (self.major, self.minor, self.build) >= (major, minor, build)
+    }
+
+    pub fn supports(&self, cap: Capability) -> bool {
+        let (major, minor, build) = cap.min_version();
+        self.at_least(major, minor, build)
+    }
+
+    // For features to fail with a message, rather than in the linker or
+    // worse, on an old Z3.
+    pub fn require(&self, cap: Capability) -> Result<(), String> {
+        if self.supports(cap) {
+            Ok(())
+        } else {
+            let (major, minor, build) = cap.min_version();
+            Err(format!("{:?} requires Z3 >= {}.{}.{}, but {} is linked",
+                        cap, major, minor, build, self))
+        }
+    }
+}
+
+impl Capability {
+    // The first release with what the feature uses. Quotes are from the
+    // release notes (RELEASE_NOTES in the Z3 sources).
+    pub fn min_version(&self) -> (u32, u32, u32) {
+        match *self {
+            // 4.8.0: "Added basic features to support Lambda bindings".
+            Capability::Lambda => (4, 8, 0),
+            // Z3_mk_array_sort_n, Z3_mk_select_n and Z3_mk_store_n came
+            // in along with lambdas.
+            Capability::ArrayN => (4, 8, 0),
+            // 4.8.0: "A 'cube' interface is exposed over the solver API".
+            Capability::Cubes => (4, 8, 0),
+            // 4.8.0: "Substantial overhaul of the spacer horn clause
+            // engine", which Chc relies on, while pdr and duality went.
+            Capability::Spacer => (4, 8, 0),
+            // 4.7.1: "uses stdbool and stdint", a breaking change for
+            // callers of the C API.
+            Capability::BoolAbi => (4, 7, 1),
+            // Not in the release notes: the table in src/proof.rs was
+            // checked against z3_api.h as of 4.8.12, and z3-sys's 4.4
+            // constants show rules being added mid-range since.
+            Capability::ProofRuleKinds => (4, 8, 12),
+            // 4.8.0: "parsers for SMT-LIB2 formulas return a vector of
+            // formulas as opposed to a conjunction of formulas".
+            Capability::ParserVectors => (4, 8, 0),
+            // Not in the release notes either: every 4.8 release up to
+            // 4.8.12 rejects Z3_mk_set_has_size, though it declares it, so
+            // this is the first release it could work in, not one it is
+            // known to.
+            Capability::SetHasSize => (4, 9, 0),
+            // Nor this one: 4.8.12 only has the boolean unicode parameter,
+            // so encoding cannot predate the release after it.
+            Capability::Encoding => (4, 8, 13)
+        }
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Version) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// By number alone; two builds of one release compare equal only if
+// their descriptions agree too.
+impl Ord for Version {
+    fn cmp(&self, other: &Version) -> Ordering {
+        (self.major, self.minor, self.build, self.revision)
+            .cmp(&(other.major, other.minor, other.build, other.revision))
+            .then_with(|| self.full.cmp(&other.full))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}.{}", self.major, self.minor, self.build, self.revision)
+    }
+}
@@ -0,0 +1,22 @@
+// Helpers shared by the integration test crates.
+
+use z3::{Ast, Context, Solver};
+
+// n + 1 pigeons in n holes: unsat, and exponentially hard for resolution,
+// which keeps a solver busy for a while.
+pub fn assert_pigeonhole<'ctx>(ctx: &'ctx Context, solver: &Solver<'ctx>, n: u32) {
+    let p: Vec<Vec<Ast>> = (0..n + 1).map(|i| {
+        (0..n).map(|j| ctx.named_bool_const(&format!("p_{}_{}", i, j))).collect()
+    }).collect();
+    for pigeon in &p {
+        let (first, rest) = pigeon.split_first().unwrap();
+        solver.assert(&first.or(&rest.iter().collect::<Vec<&Ast>>()));
+    }
+    for j in 0..n as usize {
+        for a in 0..p.len() {
+            for b in (a + 1)..p.len() {
+                solver.assert(&p[a][j].and(&[&p[b][j]]).not());
+            }
+        }
+    }
+}
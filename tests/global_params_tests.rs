@@ -0,0 +1,52 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+extern crate z3;
+use z3::*;
+
+// Global parameters are per process, so everything that touches them
+// stays in this one test.
+#[test]
+fn test_global_params() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let third = ctx.from_real(1, 3);
+    assert_eq!(third.to_string(), "(/ 1.0 3.0)");
+
+    assert_eq!(global_params::get_bool("pp.decimal"), Some(false));
+    {
+        let mut scope = global_params::scoped();
+        scope.set_bool("pp.decimal", true).unwrap();
+        scope.set_u32("pp.decimal_precision", 3).unwrap();
+        assert_eq!(global_params::get_u32("pp.decimal_precision"), Some(3));
+        info!("1/3 prints as {}", third);
+        assert_eq!(third.to_string(), "0.333?");
+    }
+    assert_eq!(global_params::get_bool("pp.decimal"), Some(false));
+    assert_eq!(global_params::get_u32("pp.decimal_precision"), Some(10));
+    assert_eq!(third.to_string(), "(/ 1.0 3.0)");
+
+    assert!(global_params::set("no_such_param", "1").is_err());
+    assert!(global_params::set("verbose", "loud").is_err());
+    assert_eq!(global_params::get_u32("verbose"), Some(0));
+    global_params::set_u32("smt.random_seed", 42).unwrap();
+    assert_eq!(global_params::get_u32("smt.random_seed"), Some(42));
+    // Z3 reads these back as 10 and 1e-05.
+    global_params::set("timeout", "0010").unwrap();
+    assert_eq!(global_params::get_u32("timeout"), Some(10));
+    global_params::set_f64("sat.restart.factor", 0.00001).unwrap();
+    assert_eq!(global_params::get_f64("sat.restart.factor"), Some(0.00001));
+    assert!(global_params::set_f64("sat.restart.factor", 0.5).is_ok());
+    assert!(global_params::set("sat.restart.factor", "fast").is_err());
+    assert_eq!(global_params::get_f64("sat.restart.factor"), Some(0.5));
+    global_params::reset_all();
+    assert_eq!(global_params::get_u32("smt.random_seed"), Some(0));
+
+    let known = global_params::known();
+    assert!(known.iter().any(|p| p.name() == "verbose" && p.kind() == ParamKind::U32));
+    assert!(known.iter().any(|p| p.name() == "pp.decimal" && p.kind() == ParamKind::Bool));
+    for p in &known {
+        assert!(global_params::get(p.name()).is_some());
+    }
+}
@@ -4,6 +4,12 @@ extern crate env_logger;
 
 extern crate z3;
 use z3::*;
+use std::panic;
+use std::thread;
+use std::time::Duration;
+
+mod common;
+use common::assert_pigeonhole;
 
 #[test]
 fn test_config() {
@@ -39,7 +45,37 @@ fn test_solving() {
 
     let solver = Solver::new(&ctx);
     solver.assert(&x.gt(&y));
-    assert!(solver.check());
+    assert_eq!(solver.check(), SatResult::Sat);
+
+    // A script that does not parse is an error, not an exit.
+    let smt2 = "(declare-const x Int) (declare-const y Int) (assert (< x y))";
+    assert!(solver.from_string(smt2).is_ok());
+    assert_eq!(solver.check(), SatResult::Unsat);
+    assert!(solver.get_model().is_none());
+    assert!(solver.from_string("(assert (< x undeclared))").is_err());
+}
+
+#[test]
+fn test_z3_errors_panic() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let solver = Solver::new(&ctx);
+    let mut params = Params::new(&ctx);
+    params.set_bool("no_such_param", true);
+
+    solver.assert(&x.gt(&ctx.from_i64(3)));
+    assert_eq!(solver.check(), SatResult::Sat);
+
+    // The panic unwinds through Z3, which stays usable afterwards.
+    let err = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        solver.set_params(&params)
+    })).unwrap_err();
+    let msg = err.downcast_ref::<String>().unwrap();
+    assert!(msg.starts_with("Z3 error: unknown parameter 'no_such_param'"), "{}", msg);
+    solver.assert(&x.lt(&ctx.from_i64(5)));
+    assert_eq!(solver.check(), SatResult::Sat);
 }
 
 #[test]
@@ -58,9 +94,9 @@ fn test_solving_for_model() {
     solver.assert(&y.gt(&zero));
     solver.assert(&y.rem(&seven)._eq(&two));
     solver.assert(&x.add(&[&two]).gt(&seven));
-    assert!(solver.check());
+    assert_eq!(solver.check(), SatResult::Sat);
 
-    let model = solver.get_model();
+    let model = solver.get_model().unwrap();
     let xv = model.eval(&x).unwrap().as_i64().unwrap();
     let yv = model.eval(&y).unwrap().as_i64().unwrap();
     info!("x: {}", xv);
@@ -70,3 +106,1039 @@ fn test_solving_for_model() {
     assert!(xv + 2 > 7);
 }
 
+
+#[test]
+fn test_optimize_soft_constraints() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let three = ctx.from_i64(3);
+    let five = ctx.from_i64(5);
+
+    let opt = Optimize::new(&ctx);
+    let big = opt.assert_soft(&x.gt(&five), 1, Some("g"));
+    let small = opt.assert_soft_real(&x.lt(&three), 1, 2, Some("g"));
+    assert_eq!(opt.check(), SatResult::Sat);
+
+    let model = opt.get_model().unwrap();
+    assert!(big.is_satisfied(&model));
+    assert!(!small.is_satisfied(&model));
+    assert_eq!(opt.get_penalty(&small).unwrap().as_real(), Some((1, 2)));
+}
+
+#[test]
+fn test_optimize_objective_bounds() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+    let three = ctx.from_i64(3);
+
+    let opt = Optimize::new(&ctx);
+    opt.assert(&x.ge(&three));
+    let xo = opt.minimize(&x);
+    let yo = opt.minimize(&y);
+    assert_eq!(opt.check(), SatResult::Sat);
+
+    match opt.get_lower(&xo).unwrap() {
+        Bound::Finite(v) => assert_eq!(v.as_i64(), Some(3)),
+        _ => panic!("expected a finite lower bound")
+    }
+    assert!(opt.get_upper(&xo).unwrap().is_finite());
+    assert!(opt.get_lower(&yo).unwrap().is_infinite());
+    assert!(opt.get_lower(&yo).unwrap().value().is_none());
+
+    let v = opt.get_lower_as_vector(&yo).unwrap();
+    assert_eq!(v.len(), 3);
+    assert_eq!(v[0].as_i64(), Some(-1));
+}
+
+#[test]
+fn test_optimize_incremental() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let a = ctx.named_bool_const("a");
+    let b = ctx.named_bool_const("b");
+    let zero = ctx.from_i64(0);
+    let ten = ctx.from_i64(10);
+
+    let opt = Optimize::new(&ctx);
+    opt.assert(&x.le(&ten));
+    opt.assert(&a.implies(&x.gt(&ten)));
+    opt.maximize(&x);
+    assert_eq!(opt.get_assertions().len(), 2);
+    assert_eq!(opt.get_objectives().len(), 1);
+
+    {
+        let scope = opt.scope();
+        scope.assert(&x.lt(&zero));
+        assert_eq!(scope.get_assertions().len(), 3);
+        assert_eq!(scope.check(), SatResult::Sat);
+        let model = scope.get_model().unwrap();
+        assert_eq!(model.eval(&x).unwrap().as_i64(), Some(-1));
+    }
+    assert_eq!(opt.get_assertions().len(), 2);
+
+    assert_eq!(opt.check_assumptions(&[&a, &b]), SatResult::Unsat);
+    let core = opt.get_unsat_core();
+    assert_eq!(core.len(), 1);
+    assert!(core[0] == a);
+
+    assert_eq!(opt.check(), SatResult::Sat);
+    assert_eq!(opt.get_model().unwrap().eval(&x).unwrap().as_i64(), Some(10));
+}
+
+fn two_objective_optimizer<'ctx>(ctx: &'ctx Context, x: &Ast<'ctx>, y: &Ast<'ctx>)
+                                 -> (Optimize<'ctx>, Objective, Objective) {
+    let zero = ctx.from_i64(0);
+    let three = ctx.from_i64(3);
+    let opt = Optimize::new(ctx);
+    opt.assert(&x.add(&[y]).le(&three));
+    opt.assert(&x.ge(&zero));
+    opt.assert(&y.ge(&zero));
+    let xo = opt.maximize(x);
+    let yo = opt.maximize(y);
+    (opt, xo, yo)
+}
+
+#[test]
+fn test_optimize_priorities() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+
+    // Lexicographic: x is maximized first, leaving nothing for y.
+    let (opt, _, _) = two_objective_optimizer(&ctx, &x, &y);
+    opt.set_priority(Priority::Lexicographic);
+    assert_eq!(opt.check(), SatResult::Sat);
+    let model = opt.get_model().unwrap();
+    assert_eq!(model.eval(&x).unwrap().as_i64(), Some(3));
+    assert_eq!(model.eval(&y).unwrap().as_i64(), Some(0));
+
+    // Box: each objective is optimized independently.
+    let (opt, xo, yo) = two_objective_optimizer(&ctx, &x, &y);
+    opt.set_priority(Priority::Box);
+    assert_eq!(opt.check(), SatResult::Sat);
+    assert_eq!(opt.get_upper(&xo).unwrap().value().unwrap().as_i64(), Some(3));
+    assert_eq!(opt.get_upper(&yo).unwrap().value().unwrap().as_i64(), Some(3));
+
+    // Pareto: every point found lies on the front x + y = 3.
+    let (opt, _, _) = two_objective_optimizer(&ctx, &x, &y);
+    let mut points = 0;
+    for (model, values) in opt.pareto_front() {
+        assert_eq!(values.len(), 2);
+        let xv = values[0].value().unwrap().as_i64().unwrap();
+        let yv = values[1].value().unwrap().as_i64().unwrap();
+        assert_eq!(model.eval(&x).unwrap().as_i64(), Some(xv));
+        assert_eq!(xv + yv, 3);
+        points += 1;
+    }
+    assert!(points > 1);
+
+    // Once the front is dropped, checks are lexicographic again.
+    assert_eq!(opt.check(), SatResult::Sat);
+    let model = opt.get_model().unwrap();
+    assert_eq!(model.eval(&x).unwrap().as_i64(), Some(3));
+    assert_eq!(model.eval(&y).unwrap().as_i64(), Some(0));
+}
+
+#[test]
+fn test_objective_of_another_optimizer() {
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+
+    let (first, xo, _) = two_objective_optimizer(&ctx, &x, &y);
+    let second = Optimize::new(&ctx);
+    second.maximize(&y);
+    assert_eq!(first.check(), SatResult::Sat);
+    assert_eq!(second.check(), SatResult::Sat);
+    assert_eq!(second.get_upper(&xo).err().unwrap(),
+               "objective belongs to a different Optimize");
+    assert!(first.get_upper(&xo).is_ok());
+}
+
+#[test]
+fn test_interrupt_handle() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+
+    // An interrupt with nothing running cancels the next check only.
+    let solver = Solver::new(&ctx);
+    solver.assert(&ctx.named_bool_const("a"));
+    ctx.interrupt_handle().interrupt();
+    assert_eq!(solver.check(), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Canceled);
+    assert_eq!(solver.check(), SatResult::Sat);
+
+    let hard = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &hard, 14);
+    let handle = ctx.interrupt_handle();
+    let t = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(200));
+        handle.interrupt();
+    });
+    assert_eq!(hard.check(), SatResult::Unknown);
+    assert_eq!(hard.get_reason_unknown(), ReasonUnknown::Canceled);
+    t.join().unwrap();
+}
+
+#[test]
+fn test_cancellation_token() {
+    let _ = env_logger::init();
+    let token = CancellationToken::new();
+    let cfg = Config::new();
+    let ctx1 = Context::new(&cfg);
+    let ctx2 = Context::new(&cfg);
+    token.register(&ctx1);
+    token.register(&ctx2);
+
+    let t = {
+        let token = token.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            token.cancel();
+        })
+    };
+    let s1 = Solver::new(&ctx1);
+    assert_pigeonhole(&ctx1, &s1, 14);
+    assert_eq!(s1.check(), SatResult::Unknown);
+    assert_eq!(s1.get_reason_unknown(), ReasonUnknown::Canceled);
+    t.join().unwrap();
+    assert!(token.is_canceled());
+
+    // ctx2 was idle when the token fired, so its next check is canceled.
+    let s2 = Solver::new(&ctx2);
+    assert_eq!(s2.check(), SatResult::Unknown);
+    assert_eq!(s2.get_reason_unknown(), ReasonUnknown::Canceled);
+}
+
+#[test]
+fn test_check_limits() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &solver, 14);
+
+    assert_eq!(solver.check_with_timeout(Duration::from_millis(100)),
+               SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Timeout);
+
+    assert_eq!(solver.check_with_limits(&Limits::new().rlimit(10000)),
+               SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::ResourceLimit);
+
+    // The rlimit is lifted again, so these run into the budget instead.
+    let mut budget = TimeBudget::new(Duration::from_millis(200), 2);
+    assert_eq!(solver.check_with_budget(&mut budget), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Timeout);
+    assert_eq!(solver.check_with_budget(&mut budget), SatResult::Unknown);
+    assert!(budget.is_exhausted());
+    assert_eq!(solver.check_with_budget(&mut budget), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Timeout);
+}
+
+#[test]
+fn test_check_limits_restore() {
+    let _ = env_logger::init();
+    let mut cfg = Config::new();
+    cfg.set_timeout_msec(100);
+    let ctx = Context::new(&cfg);
+    let rlimit = Limits::new().timeout(Duration::from_secs(60)).rlimit(10000);
+
+    // The Config timeout is back in force after a check with limits.
+    let solver = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &solver, 14);
+    assert_eq!(solver.check_with_limits(&rlimit), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::ResourceLimit);
+    assert_eq!(solver.check(), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Timeout);
+
+    // And so is one set through the solver's params.
+    let mut params = Params::new(&ctx);
+    params.set_u32("timeout", 200);
+    solver.set_params(&params);
+    assert_eq!(solver.check_with_limits(&rlimit), SatResult::Unknown);
+    assert_eq!(solver.check(), SatResult::Unknown);
+    assert_eq!(solver.get_reason_unknown(), ReasonUnknown::Timeout);
+}
+
+#[test]
+fn test_translate() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let src = Context::new(&cfg);
+    let dst = Context::new(&cfg);
+
+    let int = src.int_sort();
+    let f = src.func_decl("f", &[&int], &int);
+    let x = src.named_int_const("x");
+    let fx = f.apply(&[&x]);
+    let solver = Solver::new(&src);
+    solver.assert(&fx.gt(&x));
+    solver.assert(&x.gt(&src.from_i64(3)));
+
+    // Solve a copy of the problem in the other context.
+    let solver2 = solver.translate(&dst);
+    let f2 = f.translate(&dst);
+    assert_eq!(f2.arity(), 1);
+    let x2 = x.translate(&dst);
+    solver2.assert(&f2.apply(&[&x2])._eq(&dst.from_i64(10)));
+    assert_eq!(solver2.check(), SatResult::Sat);
+    let model = solver2.get_model().unwrap();
+    let val = model.eval(&x2).unwrap().as_i64().unwrap();
+    assert!(val > 3 && val < 10);
+
+    // The source solver never saw the extra assertion...
+    assert_eq!(solver.check(), SatResult::Sat);
+
+    // ...but the model can come back and be read there.
+    let back = model.translate(&src);
+    assert_eq!(back.eval(&fx).unwrap().as_i64(), Some(10));
+    assert_eq!(back.eval(&x).unwrap().as_i64(), Some(val));
+}
+
+#[test]
+fn test_simplify() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let one = ctx.from_i64(1);
+
+    let e = x.add(&[&one, &one]).sub(&[&one]);
+    let s = e.simplify();
+    assert!(s == e.simplify());
+    assert_eq!(s.to_string(), "(+ 1 x)");
+
+    let sq = x.add(&[&one]).mul(&[&x.add(&[&one])]);
+    let mut params = Params::new(&ctx);
+    params.set_bool("som", true);
+    let expanded = sq.simplify_with(&params);
+    assert!(expanded.to_string().contains("(* 2 x)"));
+    assert!(!sq.simplify().to_string().contains("(* 2 x)"));
+
+    assert!(ctx.simplify_help().contains("som"));
+    let descrs = ctx.simplify_param_descrs();
+    let som = descrs.iter().find(|d| d.name() == "som").unwrap();
+    assert_eq!(som.kind(), ParamKind::Bool);
+    assert!(!som.doc().is_empty());
+
+    // x > 2 implies x > 1, which only the solver-backed pass notices.
+    let f = x.gt(&ctx.from_i64(2)).implies(&x.gt(&one));
+    assert!(f.simplify() != ctx.from_bool(true));
+    assert!(f.ctx_solver_simplify() == ctx.from_bool(true));
+    assert!(x.lt(&one).and(&[&x.gt(&one)]).ctx_solver_simplify() ==
+            ctx.from_bool(false));
+}
+
+#[test]
+fn test_substitute() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let int = ctx.int_sort();
+    let one = ctx.from_i64(1);
+    let two = ctx.from_i64(2);
+
+    // Rename the state variables of a transition into the next frame.
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+    let x1 = ctx.named_int_const("x1");
+    let y1 = ctx.named_int_const("y1");
+    let step = x.add(&[&y]).gt(&one);
+    let next = step.substitute(&[(&x, &x1), (&y, &y1)]);
+    assert!(next == x1.add(&[&y1]).gt(&one));
+
+    // Instantiate a template over two variables.
+    let v0 = Ast::bound_var(&ctx, 0, &int);
+    let v1 = Ast::bound_var(&ctx, 1, &int);
+    let template = v0.mul(&[&two]).le(&v1);
+    assert!(template.substitute_vars(&[&x, &y]) == x.mul(&[&two]).le(&y));
+
+    // Inline f(a) = a + 1 and g(a, b) = f(a) * b, including inside each
+    // other and under nested applications.
+    let f = ctx.func_decl("f", &[&int], &int);
+    let g = ctx.func_decl("g", &[&int, &int], &int);
+    let f_body = v0.add(&[&one]);
+    let g_body = f.apply(&[&v0]).mul(&[&v1]);
+    let e = g.apply(&[&f.apply(&[&x]), &y]).add(&[&f.apply(&[&f.apply(&[&y])])]);
+    let inlined = e.substitute_funs(&[(&f, &f_body), (&g, &g_body)]);
+    let expected = x.add(&[&one]).add(&[&one]).mul(&[&y])
+        .add(&[&y.add(&[&one]).add(&[&one])]);
+    assert!(inlined == expected);
+
+    // Terms without the functions come back unchanged.
+    assert!(step.substitute_funs(&[(&f, &f_body)]) == step);
+}
+
+#[test]
+fn test_proofs() {
+    let _ = env_logger::init();
+    let mut cfg = Config::new();
+    cfg.set_proof_generation(true);
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let a = ctx.named_bool_const("a");
+    let premise1 = x.gt(&ctx.from_i64(2));
+    let premise2 = a.implies(&x.lt(&ctx.from_i64(1)));
+    let solver = Solver::new(&ctx);
+    solver.assert(&premise1);
+    solver.assert(&premise2);
+    solver.assert(&a);
+    assert_eq!(solver.check(), SatResult::Unsat);
+
+    let proof = solver.get_proof().unwrap();
+    let root = ProofNode::from_proof(&proof);
+    assert!(*root.conclusion() == ctx.from_bool(false));
+    assert!(root.proof() == &proof);
+    assert!(!root.premises().is_empty());
+
+    // Every assertion the refutation relies on shows up as a leaf.
+    let nodes = root.nodes();
+    let asserted: Vec<_> = nodes.iter()
+        .filter(|n| n.rule() == ProofRule::Asserted)
+        .collect();
+    for p in &[&premise1, &premise2, &a] {
+        assert!(asserted.iter().any(|n| n.conclusion() == *p));
+    }
+    assert!(asserted.iter().all(|n| n.premises().is_empty()
+                                && n.name() == "asserted"));
+
+    let text = root.to_string();
+    assert!(text.starts_with("#0 "));
+    assert!(text.contains("asserted: (> x 2)"));
+    let dot = root.to_dot();
+    assert!(dot.starts_with("digraph proof {"));
+    assert_eq!(dot.matches("[label=").count(), nodes.len());
+
+    // Congruence over a deep term gives a proof as deep; taking it
+    // apart must not take as many stack frames. Scoped assertions keep
+    // Z3 from simplifying the problem away first.
+    let f = ctx.func_decl("f", &[&ctx.int_sort()], &ctx.int_sort());
+    let (y, z) = (ctx.named_int_const("y"), ctx.named_int_const("z"));
+    let (mut fy, mut fz) = (y.clone(), z.clone());
+    for _ in 0..20000 {
+        fy = f.apply(&[&fy]);
+        fz = f.apply(&[&fz]);
+    }
+    let solver = Solver::new(&ctx);
+    solver.push();
+    solver.assert(&y._eq(&z));
+    solver.assert(&fy._eq(&fz).not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+    let root = ProofNode::from_proof(&solver.get_proof().unwrap());
+    assert!(root.nodes().len() > 20000);
+    drop(root);
+
+    // Without proof generation there is nothing to hand back.
+    let plain = Context::new(&Config::new());
+    let solver = Solver::new(&plain);
+    solver.assert(&plain.from_bool(false));
+    assert_eq!(solver.check(), SatResult::Unsat);
+    assert!(solver.get_proof().is_none());
+}
+
+#[test]
+fn test_consequences() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let a = ctx.named_bool_const("a");
+    let b = ctx.named_bool_const("b");
+    let c = ctx.named_bool_const("c");
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+    let solver = Solver::new(&ctx);
+    solver.assert(&a.implies(&b));
+    solver.assert(&b.implies(&x._eq(&ctx.from_i64(3))));
+    solver.assert(&c.not());
+    solver.assert(&y.gt(&ctx.from_i64(0)));
+
+    let (res, found) = solver.consequences(&[&a], &[&b, &c, &x, &y]);
+    assert_eq!(res, SatResult::Sat);
+    let value_of = |v: &Ast| {
+        found.iter().find(|f| f.variable() == v).map(|f| f.value().to_string())
+    };
+    assert_eq!(value_of(&b), Some("true".to_string()));
+    assert_eq!(value_of(&c), Some("false".to_string()));
+    assert_eq!(value_of(&x), Some("3".to_string()));
+    assert_eq!(value_of(&y), None);
+    let via_a = found.iter().find(|f| f.variable() == &x).unwrap();
+    assert!(via_a.assumptions().len() == 1 && via_a.assumptions()[0] == a);
+    let fixed = found.iter().find(|f| f.variable() == &c).unwrap();
+    assert!(fixed.assumptions().is_empty());
+
+    // Without a, only c is settled.
+    let backbone = solver.backbone(&[&b, &c, &x, &y]).unwrap();
+    assert_eq!(backbone.len(), 1);
+    assert!(backbone[0].0 == c && backbone[0].1 == ctx.from_bool(false));
+
+    // A conjunction passed as one assumption stays one assumption.
+    let d = ctx.named_bool_const("d");
+    let e = ctx.named_bool_const("e");
+    let de = d.and(&[&e]);
+    let z = ctx.named_int_const("z");
+    let conj = Solver::new(&ctx);
+    conj.assert(&de.implies(&z._eq(&ctx.from_i64(5))));
+    let (res, found) = conj.consequences(&[&de], &[&z]);
+    assert_eq!(res, SatResult::Sat);
+    assert_eq!(found.len(), 1);
+    assert!(found[0].assumptions().len() == 1 && found[0].assumptions()[0] == de);
+
+    solver.assert(&a);
+    solver.assert(&x.lt(&ctx.from_i64(0)));
+    assert!(solver.backbone(&[&x]).is_none());
+}
+
+#[test]
+fn test_cubes() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new_for_logic(&ctx, "QF_FD");
+    assert_pigeonhole(&ctx, &solver, 5);
+
+    let cubes: Vec<Vec<Ast>> = solver.cubes(&[], CubeCutoff::Depth(3)).unwrap().collect();
+    assert!(cubes.len() > 1);
+    assert!(cubes.iter().all(|c| !c.is_empty() && c.len() <= 3));
+
+    // Each cube is a refinement of the problem, and like it unsat.
+    for cube in &cubes {
+        let sub = solver.translate(&ctx);
+        for lit in cube {
+            sub.assert(lit);
+        }
+        assert_eq!(sub.check(), SatResult::Unsat);
+    }
+
+    // Splitting can be confined to some of the variables.
+    let p00 = ctx.named_bool_const("p_0_0");
+    let p01 = ctx.named_bool_const("p_0_1");
+    let cubes: Vec<Vec<Ast>> = solver.cubes(&[&p00, &p01], CubeCutoff::Depth(1)).unwrap()
+        .collect();
+    assert!(!cubes.is_empty());
+    for lit in cubes.iter().flat_map(|c| c.iter()) {
+        assert!(*lit == p00 || *lit == p01 || *lit == p00.not() || *lit == p01.not());
+    }
+
+    // A problem refuted outright has no cubes at all.
+    let trivial = Solver::new(&ctx);
+    trivial.assert(&p00);
+    trivial.assert(&p00.not());
+    assert_eq!(trivial.cubes(&[], CubeCutoff::Depth(2)).unwrap().count(), 0);
+}
+
+#[test]
+fn test_models() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+    let solver = Solver::new(&ctx);
+    solver.assert(&x.ge(&ctx.from_i64(0)));
+    solver.assert(&x.lt(&ctx.from_i64(4)));
+    solver.assert(&y.gt(&x));
+
+    // y is unbounded, but projected onto x there are just four models.
+    let mut xs: Vec<i64> = Vec::new();
+    {
+        let mut models = solver.models(&[&x]);
+        for m in &mut models {
+            xs.push(m.eval(&x).unwrap().as_i64().unwrap());
+        }
+        assert!(models.is_complete());
+    }
+    xs.sort();
+    assert_eq!(xs, vec![0, 1, 2, 3]);
+
+    {
+        let mut models = solver.models(&[&x, &y]).limit(10);
+        assert_eq!(models.by_ref().count(), 10);
+        assert!(!models.is_complete());
+    }
+
+    // The blocking clauses are gone again.
+    solver.assert(&x._eq(&ctx.from_i64(0)));
+    assert_eq!(solver.check(), SatResult::Sat);
+    assert_eq!(solver.models(&[]).count(), 1);
+    solver.assert(&y.lt(&x));
+    assert_eq!(solver.models(&[&x]).count(), 0);
+}
+
+#[test]
+fn test_fixedpoint() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let node = ctx.finite_domain_sort("Node", 8);
+    let b = ctx.bool_sort();
+    let edge = ctx.func_decl("edge", &[&node, &node], &b);
+    let path = ctx.func_decl("path", &[&node, &node], &b);
+
+    let fp = Fixedpoint::new(&ctx);
+    fp.set_engine(FixedpointEngine::Datalog);
+    fp.register_relation(&edge);
+    fp.register_relation(&path);
+    for &(from, to) in &[(0, 1), (1, 2), (2, 3), (5, 6)] {
+        fp.add_fact(&edge, &[from, to]);
+    }
+    let x = Ast::new_const(&ctx.str_sym("x"), &node);
+    let y = Ast::new_const(&ctx.str_sym("y"), &node);
+    let z = Ast::new_const(&ctx.str_sym("z"), &node);
+    fp.add_rule(&Ast::forall_const(&ctx, &[&x, &y],
+                                   &edge.apply(&[&x, &y])
+                                   .implies(&path.apply(&[&x, &y]))),
+                Some("base"));
+    fp.add_rule(&Ast::forall_const(&ctx, &[&x, &y, &z],
+                                   &path.apply(&[&x, &y])
+                                   .and(&[&edge.apply(&[&y, &z])])
+                                   .implies(&path.apply(&[&x, &z]))),
+                Some("step"));
+    assert_eq!(fp.get_rules().len(), 2);
+
+    let n = |i| Ast::from_u64_sorted(&node, i);
+    assert_eq!(fp.query(&path.apply(&[&n(0), &n(3)])), SatResult::Sat);
+    assert_eq!(fp.query(&path.apply(&[&n(3), &n(0)])), SatResult::Unsat);
+    assert_eq!(fp.query(&path.apply(&[&n(0), &n(6)])), SatResult::Unsat);
+
+    // Everything reachable from 0.
+    let q = Ast::exists_const(&ctx, &[&y], &path.apply(&[&n(0), &y]));
+    assert_eq!(fp.query(&q), SatResult::Sat);
+    let answer = fp.get_answer().unwrap().to_string();
+    for i in 1..4 {
+        assert!(answer.contains(&format!(" {})", i)), "{}", answer);
+    }
+    assert!(!answer.contains(" 5)") && !answer.contains(" 6)"));
+    assert!(fp.to_string().contains("path"));
+
+    // Spacer works over integers and proves unreachability with an
+    // invariant.
+    let i = ctx.int_sort();
+    let inv = ctx.func_decl("inv", &[&i], &b);
+    let fp = Fixedpoint::new(&ctx);
+    fp.set_engine(FixedpointEngine::Spacer);
+    fp.register_relation(&inv);
+    let v = ctx.named_int_const("v");
+    fp.add_rule(&Ast::forall_const(&ctx, &[&v],
+                                   &v._eq(&ctx.from_i64(0))
+                                   .implies(&inv.apply(&[&v]))), None);
+    fp.add_rule(&Ast::forall_const(&ctx, &[&v],
+                                   &inv.apply(&[&v])
+                                   .implies(&inv.apply(&[&v.add(&[&ctx.from_i64(2)])]))),
+                None);
+    let bad = inv.apply(&[&ctx.from_i64(7)]);
+    assert_eq!(fp.query(&bad), SatResult::Unsat);
+    assert!(fp.get_answer().is_some());
+    assert_eq!(fp.query(&inv.apply(&[&ctx.from_i64(8)])), SatResult::Sat);
+}
+
+#[test]
+fn test_chc() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let i = ctx.int_sort();
+    let chc = Chc::new(&ctx).unwrap();
+    let inv = chc.predicate("inv", &[&i]);
+    let x = ctx.named_int_const("x");
+    let zero = ctx.from_i64(0);
+    assert!(inv.is_uninterpreted() && x.decl().unwrap().is_uninterpreted());
+    assert!(!x.add(&[&zero]).decl().unwrap().is_uninterpreted());
+    chc.rule(&x._eq(&zero).implies(&inv.apply(&[&x])));
+    chc.rule(&inv.apply(&[&x]).implies(&inv.apply(&[&x.add(&[&ctx.from_i64(2)])])));
+
+    // x stays even, so 7 is never reached.
+    let bad = inv.apply(&[&x]).and(&[&x._eq(&ctx.from_i64(7))]);
+    assert_eq!(chc.query(&bad), ChcResult::Safe);
+    let invariant = chc.invariant(&inv).unwrap().substitute_vars(&[&x]);
+    let solver = Solver::new(&ctx);
+    solver.assert(&invariant);
+    solver.assert(&x._eq(&ctx.from_i64(7)));
+    assert_eq!(solver.check(), SatResult::Unsat);
+    let solver = Solver::new(&ctx);
+    solver.assert(&x._eq(&zero).implies(&invariant).not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+    assert_eq!(chc.invariants().len(), 1);
+
+    let bad = inv.apply(&[&x]).and(&[&x._eq(&ctx.from_i64(8))]);
+    assert_eq!(chc.query(&bad), ChcResult::Unsafe);
+    assert!(chc.counterexample().is_some());
+
+    // The same system, with its query as a clause with a false head.
+    let smt2 = "(set-logic HORN)
+                (declare-fun inv (Int) Bool)
+                (declare-fun odd (Int) Bool)
+                (assert (forall ((x Int)) (=> (= x 0) (inv x))))
+                (assert (forall ((x Int)) (=> (inv x) (inv (+ x 2)))))
+                (assert (forall ((x Int)) (=> (inv x) (odd (+ x 1)))))
+                (assert (forall ((x Int)) (=> (and (odd x) (= x 10)) false)))
+                (check-sat)";
+    let chc = Chc::from_string(&ctx, smt2).unwrap();
+    let mut names : Vec<String> = chc.predicates().iter().map(|p| p.name()).collect();
+    names.sort();
+    assert_eq!(names, vec!["inv", "odd"]);
+    assert_eq!(chc.check(), ChcResult::Safe);
+    assert_eq!(chc.invariants().len(), 2);
+    let chc = Chc::from_string(&ctx, &smt2.replace("10", "11")).unwrap();
+    assert_eq!(chc.check(), ChcResult::Unsafe);
+
+    let path = std::env::temp_dir()
+        .join(format!("z3_test_chc_{}.smt2", std::process::id()));
+    std::fs::write(&path, smt2).unwrap();
+    let chc = Chc::from_file(&ctx, path.to_str().unwrap()).unwrap();
+    assert_eq!(chc.check(), ChcResult::Safe);
+    std::fs::remove_file(&path).unwrap();
+
+    let err = Chc::from_string(&ctx, "(assert (inv")
+        .err().unwrap();
+    assert!(err.starts_with("line 1 column 13:"), "{}", err);
+    assert!(Chc::from_file(&ctx, "/nonexistent.smt2").is_err());
+
+    // Clauses far deeper than the call stack.
+    let chc = Chc::new(&ctx).unwrap();
+    let inv = chc.predicate("inv", &[&i]);
+    let mut deep = x.clone();
+    for _ in 0..100000 {
+        deep = deep.add(&[&ctx.from_i64(1)]);
+    }
+    chc.rule(&inv.apply(&[&x]).implies(&inv.apply(&[&deep])));
+    assert_eq!(chc.predicates().len(), 1);
+}
+
+#[test]
+fn test_pseudo_boolean() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+
+    // Exactly one version of each of three packages, with b's 2.0
+    // ruled out and a's 1.1 requiring it.
+    let pkgs = ["a", "b", "c"];
+    let vers: Vec<Vec<Ast>> = pkgs.iter().map(|p| {
+        (0..3).map(|v| ctx.named_bool_const(&format!("{}-1.{}", p, v))).collect()
+    }).collect();
+    let solver = Solver::new(&ctx);
+    for vs in &vers {
+        let vs: Vec<&Ast> = vs.iter().collect();
+        solver.assert(&Ast::at_most(&ctx, &vs, 1));
+        solver.assert(&Ast::at_least(&ctx, &vs, 1));
+    }
+    solver.assert(&vers[0][1].implies(&vers[1][2]));
+    solver.assert(&vers[1][2].not());
+    // Prefer newer versions: weight each by its minor number.
+    let weighted: Vec<(&Ast, i32)> = vers.iter()
+        .flat_map(|vs| vs.iter().enumerate().map(|(i, v)| (v, i as i32)))
+        .collect();
+    solver.assert(&Ast::pb_ge(&ctx, &weighted, 5));
+    assert_eq!(solver.check(), SatResult::Sat);
+    let model = solver.get_model().unwrap();
+    let chosen: Vec<usize> = vers.iter().map(|vs| {
+        let on: Vec<usize> = (0..3)
+            .filter(|&i| model.eval(&vs[i]).unwrap().as_bool().unwrap())
+            .collect();
+        assert_eq!(on.len(), 1);
+        on[0]
+    }).collect();
+    assert_eq!(chosen, vec![2, 1, 2]);
+
+    solver.assert(&Ast::pb_le(&ctx, &weighted, 4));
+    assert_eq!(solver.check(), SatResult::Unsat);
+
+    let p = ctx.named_bool_const("p");
+    let q = ctx.named_bool_const("q");
+    let solver = Solver::new(&ctx);
+    solver.assert(&Ast::pb_eq(&ctx, &[(&p, 2), (&q, 3)], 3));
+    assert_eq!(solver.check(), SatResult::Sat);
+    let model = solver.get_model().unwrap();
+    assert_eq!(model.eval(&p).unwrap().as_bool(), Some(false));
+    assert_eq!(model.eval(&q).unwrap().as_bool(), Some(true));
+}
+
+#[test]
+fn test_arrays() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let int = ctx.int_sort();
+    let i = ctx.named_int_const("i");
+    let j = ctx.named_int_const("j");
+    let one = ctx.from_i64(1);
+
+    // A zeroed memory with one word written.
+    let zero = Ast::const_array(&int, &ctx.from_i64(0));
+    let mem = zero.store(&one, &ctx.from_i64(7));
+    let solver = Solver::new(&ctx);
+    solver.assert(&i._eq(&one).not());
+    solver.assert(&mem.select(&i)._eq(&ctx.from_i64(0)).not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+    let solver = Solver::new(&ctx);
+    solver.assert(&mem.array_default()._eq(&ctx.from_i64(0)).not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+
+    // Squares, by comprehension, either way of binding the index.
+    let squares = Ast::lambda_const(&ctx, &[&i], &i.mul(&[&i])).unwrap();
+    let x = Ast::bound_var(&ctx, 0, &int);
+    let squares2 = Ast::lambda(&ctx, &[(&ctx.str_sym("x"), &int)], &x.mul(&[&x])).unwrap();
+    assert_eq!(squares.select(&ctx.from_i64(5)).simplify().as_i64(), Some(25));
+    assert_eq!(squares2.select(&ctx.from_i64(6)).simplify().as_i64(), Some(36));
+
+    // Pointwise addition.
+    let int_array = ctx.array_sort(&int, &int);
+    let a = ctx.named_const("a", &int_array);
+    let plus = i.add(&[&j]).decl().unwrap();
+    let sum = Ast::array_map(&plus, &[&a, &squares]);
+    let solver = Solver::new(&ctx);
+    solver.assert(&a.select(&ctx.from_i64(3))._eq(&one));
+    solver.assert(&sum.select(&ctx.from_i64(3))._eq(&ctx.from_i64(10)).not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+
+    // Arrays that differ do so at their extensionality witness.
+    let b = ctx.named_const("b", &int_array);
+    let solver = Solver::new(&ctx);
+    solver.assert(&a._eq(&b).not());
+    solver.assert(&a.select(&a.array_ext(&b))._eq(&b.select(&a.array_ext(&b))));
+    assert_eq!(solver.check(), SatResult::Unsat);
+
+    // A two-dimensional table.
+    let table_sort = ctx.array_sort_n(&[&int, &int], &ctx.bool_sort()).unwrap();
+    let t = ctx.named_const("t", &table_sort);
+    let t2 = t.store_n(&[&i, &j], &ctx.from_bool(true)).unwrap();
+    let solver = Solver::new(&ctx);
+    solver.assert(&t2.select_n(&[&i, &j]).unwrap().not());
+    assert_eq!(solver.check(), SatResult::Unsat);
+}
+
+#[test]
+fn test_sets() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let int = ctx.int_sort();
+    let set = ctx.set_sort(&int);
+    let n = |i| ctx.from_i64(i);
+
+    // Users 1 and 2 are admins; readers are everyone but the banned,
+    // and user 3 is banned.
+    let admins = ctx.named_const("admins", &set);
+    let readers = ctx.named_const("readers", &set);
+    let banned = ctx.named_const("banned", &set);
+    let solver = Solver::new(&ctx);
+    solver.assert(&admins._eq(&Ast::empty_set(&int).set_add(&n(1)).set_add(&n(2))));
+    solver.assert(&banned._eq(&Ast::empty_set(&int).set_add(&n(3))));
+    solver.assert(&readers._eq(&Ast::full_set(&int).set_difference(&banned)));
+    assert_eq!(solver.check(), SatResult::Sat);
+    let model = solver.get_model().unwrap();
+
+    match model.eval_set(&admins) {
+        Some(SetValue::Finite(members)) => {
+            let mut members: Vec<i64> = members.iter().map(|m| m.as_i64().unwrap()).collect();
+            members.sort();
+            assert_eq!(members, vec![1, 2]);
+        }
+        _ => panic!("admins should be finite")
+    }
+    let readers = model.eval_set(&readers).unwrap();
+    assert!(!readers.is_finite());
+    assert!(readers.contains(&n(1)) && readers.contains(&n(42)));
+    assert!(!readers.contains(&n(3)));
+    let none = model.eval_set(&Ast::empty_set(&int)).unwrap();
+    assert!(none.is_finite() && !none.contains(&n(0)));
+
+    // Sizes, counted over a known universe of users.
+    let users: Vec<Ast> = (1..6).map(n).collect();
+    let users: Vec<&Ast> = users.iter().collect();
+    let s = ctx.named_const("s", &set);
+    let solver = Solver::new(&ctx);
+    solver.assert(&s.set_size_within(&users)._eq(&n(2)));
+    solver.assert(&n(5).set_member(&s));
+    solver.assert(&n(6).set_member(&s).not());
+    solver.assert(&n(1).set_member(&s).not());
+    assert_eq!(solver.check(), SatResult::Sat);
+    solver.assert(&n(2).set_member(&s));
+    solver.assert(&n(3).set_member(&s));
+    assert_eq!(solver.check(), SatResult::Unsat);
+    assert_eq!(s.set_size_within(&[]).as_i64(), Some(0));
+    // Z3 4.8 rejects set-has-size outright.
+    let has_two = s.set_has_size(&n(2));
+    if z3::version().supports(Capability::SetHasSize) {
+        let solver = Solver::new(&ctx);
+        solver.assert(&has_two.unwrap());
+        assert_eq!(solver.check(), SatResult::Sat);
+    } else {
+        assert!(has_two.is_none());
+    }
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)]
+fn test_sort_introspection() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let int = ctx.int_sort();
+    let bv = ctx.bitvector_sort(16);
+    let arr = ctx.array_sort(&int, &bv);
+
+    assert_eq!(int.kind(), SortKind::Int);
+    assert_eq!(ctx.bool_sort().kind(), SortKind::Bool);
+    assert_eq!(ctx.real_sort().kind(), SortKind::Real);
+    assert_eq!(bv.kind(), SortKind::BitVector);
+    assert_eq!(bv.bv_size(), Some(16));
+    assert_eq!(int.bv_size(), None);
+    assert_eq!(arr.kind(), SortKind::Array);
+    assert!(arr.array_domain().unwrap() == int);
+    assert!(arr.array_range().unwrap() == bv);
+    assert!(int.array_domain().is_none());
+    let node = Sort::uninterpretd(&ctx, &ctx.str_sym("Node"));
+    assert_eq!(node.kind(), SortKind::Uninterpreted);
+    assert_eq!(node.name(), "Node");
+    assert_eq!(int.name(), "Int");
+    assert_eq!(arr.to_string(), "(Array Int (_ BitVec 16))");
+
+    // Sorts of expressions, equal however they were made.
+    let x = ctx.named_int_const("x");
+    assert!(x.get_sort() == int);
+    assert!(x.get_sort() == ctx.int_sort());
+    assert!(int != ctx.real_sort());
+    assert!(ctx.bitvector_sort(8) != bv);
+
+    let mut widths = std::collections::HashMap::new();
+    for w in &[8, 16, 8, 32, 16] {
+        *widths.entry(ctx.bitvector_sort(*w)).or_insert(0) += 1;
+    }
+    assert_eq!(widths.len(), 3);
+    assert_eq!(widths[&bv], 2);
+}
+
+#[test]
+#[allow(clippy::mutable_key_type)]
+fn test_symbol_introspection() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let s = ctx.str_sym("alice");
+    let i = ctx.int_sym(7);
+    assert_eq!(s.kind(), SymbolKind::Str);
+    assert_eq!(i.kind(), SymbolKind::Int);
+    assert_eq!(s.as_str(), Some("alice".to_string()));
+    assert_eq!(s.as_int(), None);
+    assert_eq!(i.as_int(), Some(7));
+    assert_eq!(i.as_str(), None);
+    assert_eq!(s.to_string(), "alice");
+    assert_eq!(i.to_string(), "k!7");
+    assert_eq!(SymbolName::from(&i), SymbolName::Int(7));
+    assert!(s == ctx.str_sym("alice"));
+    assert!(s != ctx.str_sym("bob"));
+    assert!(Symbol::from_name(&ctx, &s.name()) == s);
+
+    // Map the constants of a model back to the objects they stand for.
+    let users = vec!["alice", "bob"];
+    let int = ctx.int_sort();
+    let mut consts = std::collections::HashMap::new();
+    for u in &users {
+        consts.insert(ctx.str_sym(u), u);
+    }
+    let solver = Solver::new(&ctx);
+    let alice = ctx.named_const("alice", &int);
+    let bob = ctx.named_const("bob", &int);
+    let n = ctx.int_sym(3);
+    let anon = Ast::new_const(&n, &int);
+    solver.assert(&alice.gt(&bob));
+    solver.assert(&anon._eq(&bob));
+    assert_eq!(solver.check(), SatResult::Sat);
+    let model = solver.get_model().unwrap();
+    let mut found: Vec<SymbolName> = model.consts().iter().map(|c| c.symbol().name()).collect();
+    found.sort_by_key(|n| n.to_string());
+    assert_eq!(found, vec![SymbolName::Str("alice".to_string()),
+                           SymbolName::Str("bob".to_string()),
+                           SymbolName::Int(3)]);
+    for c in model.consts() {
+        if let Some(u) = consts.get(&c.symbol()) {
+            assert_eq!(&c.name(), *u);
+        }
+    }
+}
+
+#[test]
+fn test_config_builder() {
+    let _ = env_logger::init();
+    let cfg = ConfigBuilder::new()
+        .proof(true)
+        .unsat_core(true)
+        .model_validate(true)
+        .well_sorted_check(true)
+        .smtlib2_compliant(true)
+        .timeout_msec(5000)
+        .random_seed(7)
+        .threads(1)
+        .build()
+        .unwrap();
+    assert_eq!(cfg.get_param_value("proof"), Some("true".to_string()));
+    assert_eq!(cfg.get_param_value("timeout"), Some("5000".to_string()));
+    assert_eq!(cfg.get_param_value("auto_config"), Some("true".to_string()));
+    assert_eq!(cfg.get_param_value("no_such_param"), None);
+    let effective = cfg.effective_params();
+    assert!(effective.contains(&("unsat_core".to_string(), "true".to_string())));
+    assert!(effective.contains(&("trace".to_string(), "false".to_string())));
+    assert!(effective.contains(&("random_seed".to_string(), "7".to_string())));
+    assert_eq!(cfg.get_param_value("threads"), Some("1".to_string()));
+    assert_eq!(Config::new().get_param_value("random_seed"), None);
+
+    // Proofs are on, so a refutation comes with one.
+    let ctx = Context::new(&cfg);
+    let x = ctx.named_int_const("x");
+    let solver = Solver::new(&ctx);
+    solver.assert(&x.gt(&ctx.from_i64(1)));
+    solver.assert(&x.lt(&ctx.from_i64(0)));
+    assert_eq!(solver.check(), SatResult::Unsat);
+    assert!(solver.get_proof().is_some());
+
+    assert!(ConfigBuilder::new().param("no_such_param", "1").build().is_err());
+    assert!(ConfigBuilder::new().param("smt.random_seed", "1").build().is_err());
+    assert!(ConfigBuilder::new().param("proof", "maybe").build().is_err());
+    assert!(ConfigBuilder::new().param("timeout", "-1").build().is_err());
+    assert!(ConfigBuilder::new().param("model", "false").build().is_ok());
+
+    let ascii = ConfigBuilder::new().encoding(Encoding::Ascii).build();
+    if z3::version().supports(Capability::Encoding) {
+        assert_eq!(ascii.unwrap().get_param_value("encoding"), Some("ascii".to_string()));
+        assert!(ConfigBuilder::new().param("encoding", "utf8").build().is_err());
+    } else {
+        assert!(ascii.err().unwrap().starts_with("Encoding requires Z3 >= 4.8.13"));
+    }
+}
+
+#[test]
+fn test_version() {
+    let _ = env_logger::init();
+    let v = z3::version();
+    info!("linked against Z3 {} ({})", v, v.full());
+    assert!(v.major() >= 4);
+    assert!(v.full().contains(&format!("{}.{}.{}", v.major(), v.minor(), v.build())));
+    assert!(v.to_string().starts_with(&format!("{}.{}.", v.major(), v.minor())));
+    assert!(v.at_least(4, 0, 0));
+    assert!(!v.at_least(v.major() + 1, 0, 0));
+
+    // Contexts need the C99 bool ABI, so any Z3 that got this far has it.
+    assert!(v.supports(Capability::BoolAbi));
+    assert_eq!(Capability::BoolAbi.min_version(), (4, 7, 1));
+    assert_eq!(Capability::Spacer.min_version(), (4, 8, 0));
+    if v.supports(Capability::Lambda) {
+        assert!(v.require(Capability::Lambda).is_ok());
+    } else {
+        let e = v.require(Capability::Lambda).unwrap_err();
+        assert!(e.starts_with("Lambda requires Z3 >= 4.8.0"));
+    }
+    assert!(v == z3::version());
+}
@@ -0,0 +1,199 @@
+extern crate log;
+extern crate env_logger;
+
+extern crate z3;
+use z3::*;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod common;
+use common::assert_pigeonhole;
+
+fn solve_pigeonhole(n: u32) {
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &solver, n);
+    assert_eq!(solver.check(), SatResult::Unsat);
+}
+
+#[test]
+fn test_contexts_do_not_block_each_other() {
+    let _ = env_logger::init();
+
+    // A long check in one context...
+    let busy = thread::spawn(|| {
+        let cfg = Config::new();
+        let ctx = Context::new(&cfg);
+        let solver = Solver::new(&ctx);
+        assert_pigeonhole(&ctx, &solver, 14);
+        solver.check_with_timeout(Duration::from_secs(2))
+    });
+    thread::sleep(Duration::from_millis(200));
+
+    // ...leaves another context free to work in the meantime.
+    let start = Instant::now();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+    solver.assert(&ctx.named_int_const("x").gt(&ctx.from_i64(0)));
+    assert_eq!(solver.check(), SatResult::Sat);
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    assert_eq!(busy.join().unwrap(), SatResult::Unknown);
+}
+
+#[test]
+fn test_context_is_send() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let t = thread::spawn(move || {
+        let solver = Solver::new(&ctx);
+        solver.assert(&ctx.named_bool_const("a"));
+        solver.check()
+    });
+    assert_eq!(t.join().unwrap(), SatResult::Sat);
+}
+
+// A benchmark rather than a test, as wall-clock comparisons are at the
+// mercy of whatever else the machine is doing; run it with
+// `cargo test --test parallel_tests -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn bench_parallel_contexts() {
+    let _ = env_logger::init();
+    let jobs = 4;
+    let n = 8;
+
+    let start = Instant::now();
+    for _ in 0..jobs {
+        solve_pigeonhole(n);
+    }
+    let sequential = start.elapsed();
+
+    let start = Instant::now();
+    let workers: Vec<_> = (0..jobs)
+        .map(|_| thread::spawn(move || solve_pigeonhole(n)))
+        .collect();
+    for w in workers {
+        w.join().unwrap();
+    }
+    let parallel = start.elapsed();
+
+    let cores = thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let ratio = sequential.as_secs_f64() / parallel.as_secs_f64();
+    println!("{} jobs on {} cores: sequential {:?}, parallel {:?}, speedup {:.2}",
+             jobs, cores, sequential, parallel, ratio);
+    // Contexts that still serialized on a shared lock would take about
+    // as long either way; any real concurrency beats that.
+    if cores > 1 {
+        assert!(parallel < sequential);
+    }
+}
+
+#[test]
+fn test_portfolio() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &solver, 6);
+
+    // "skip" never decides anything, and the second tactic does not exist;
+    // neither may keep the others from answering.
+    let portfolio = Portfolio::new(&solver)
+        .strategy(Strategy::new("gives-up").tactic("skip"))
+        .strategy(Strategy::new("bogus").tactic("no-such-tactic"))
+        .strategy(Strategy::new("default"))
+        .strategy(Strategy::new("seeded").seed(7)
+                  .param("smt.phase_selection", ParamValue::U32(5)));
+    let res = portfolio.run();
+    assert_eq!(res.result(), SatResult::Unsat);
+    let winner = res.winner().unwrap();
+    assert!(winner >= 2);
+    assert_eq!(res.name(), Some(portfolio.strategies()[winner].name()));
+    assert!(res.model().is_none());
+
+    let x = ctx.named_int_const("x");
+    let sat = Solver::new(&ctx);
+    sat.assert(&x.gt(&ctx.from_i64(41)));
+    sat.assert(&x.lt(&ctx.from_i64(43)));
+    let res = Portfolio::new(&sat)
+        .strategy(Strategy::new("lia").logic("QF_LIA"))
+        .strategy(Strategy::new("smt").tactic("smt"))
+        .run();
+    assert_eq!(res.result(), SatResult::Sat);
+    let model = res.model().unwrap();
+    assert_eq!(model.eval(&x).and_then(|v| v.as_i64()), Some(42));
+
+    let res = Portfolio::new(&solver)
+        .strategy(Strategy::new("gives-up").tactic("skip"))
+        .run();
+    assert_eq!(res.result(), SatResult::Unknown);
+    assert_eq!(res.winner(), None);
+}
+
+#[test]
+fn test_portfolio_interrupts_losers() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let solver = Solver::new(&ctx);
+    assert_pigeonhole(&ctx, &solver, 14);
+    let x = ctx.named_int_const("x");
+    let y = ctx.named_int_const("y");
+    solver.assert(&x.add(&[&y]).gt(&ctx.from_i64(10)));
+    solver.assert(&x.lt(&ctx.from_i64(3)));
+    solver.assert(&y.lt(&ctx.from_i64(3)));
+
+    // The arithmetic is contradictory, which the SMT core sees at once;
+    // the plain SAT tactic is stuck in the pigeonhole until interrupted.
+    // The timeout only keeps a broken interrupt from hanging the test.
+    let start = Instant::now();
+    let res = Portfolio::new(&solver)
+        .strategy(Strategy::new("sat").tactic("sat"))
+        .strategy(Strategy::new("smt").tactic("smt"))
+        .limits(Limits::new().timeout(Duration::from_secs(60)))
+        .run();
+    assert_eq!(res.result(), SatResult::Unsat);
+    assert_eq!(res.name(), Some("smt"));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_cube_and_conquer() {
+    let _ = env_logger::init();
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+
+    let solver = Solver::new_for_logic(&ctx, "QF_FD");
+    assert_pigeonhole(&ctx, &solver, 7);
+    let (res, cube) = solver.cube_and_conquer(&[], CubeCutoff::Depth(3), 4).unwrap();
+    assert_eq!(res, SatResult::Unsat);
+    assert!(cube.is_none());
+
+    // As many holes as pigeons; the winning cube then leads straight to
+    // a model.
+    let sat = Solver::new_for_logic(&ctx, "QF_FD");
+    let p: Vec<Vec<Ast>> = (0..7).map(|i| {
+        (0..7).map(|j| ctx.named_bool_const(&format!("p_{}_{}", i, j))).collect()
+    }).collect();
+    for pigeon in &p {
+        let (first, rest) = pigeon.split_first().unwrap();
+        sat.assert(&first.or(&rest.iter().collect::<Vec<&Ast>>()));
+    }
+    for (a, pa) in p.iter().enumerate() {
+        for pb in &p[a + 1..] {
+            for (x, y) in pa.iter().zip(pb) {
+                sat.assert(&x.and(&[y]).not());
+            }
+        }
+    }
+    let (res, cube) = sat.cube_and_conquer(&[], CubeCutoff::Depth(3), 4).unwrap();
+    assert_eq!(res, SatResult::Sat);
+    for lit in &cube.unwrap() {
+        sat.assert(lit);
+    }
+    assert_eq!(sat.check(), SatResult::Sat);
+}
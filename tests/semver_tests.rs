@@ -32,17 +32,14 @@ impl Spec {
 type SpecMap = HashMap<String, Vec<Spec>>;
 
 fn get_version(sm: &SpecMap, pkg: &str, ver: usize) -> Option<Version> {
-    match sm.get(pkg) {
-        None => None,
-        Some(ref specs) => Some(specs[ver].vers.clone())
-    }
+    sm.get(pkg).map(|specs| specs[ver].vers.clone())
 }
 
 fn version_index(sm: &SpecMap, pkg: &str, ver: &str) -> Option<usize> {
     let ver = Version::parse(ver).unwrap();
     match sm.get(pkg) {
         None => None,
-        Some(ref specs) =>
+        Some(specs) =>
             specs.iter().position(|spec| spec.vers == ver)
     }
 }
@@ -51,7 +48,7 @@ fn first_version_req_index(sm: &SpecMap, pkg: &str,
                            req: &VersionReq) -> Option<usize> {
     match sm.get(pkg) {
         None => None,
-        Some(ref specs) =>
+        Some(specs) =>
             specs.iter().position(|spec| req.matches(&spec.vers))
     }
 }
@@ -60,20 +57,12 @@ fn last_version_req_index(sm: &SpecMap, pkg: &str,
                           req: &VersionReq) -> Option<usize> {
     match sm.get(pkg) {
         None => None,
-        Some(ref specs) =>
+        Some(specs) =>
             specs.iter().rposition(|spec| req.matches(&spec.vers))
     }
 }
 
-#[test]
-fn test_solve_simple_semver_example() {
-
-    // This is a little example of solving version constraints the way cargo
-    // might someday want to. It uses the optimizer portion of Z3.
-    // see: https://github.com/rust-lang/cargo/issues/2064
-
-    let _ = env_logger::init();
-
+fn example_spec_map() -> SpecMap {
     let mut smap : SpecMap = HashMap::new();
 
     smap.insert("postgres".to_string(),
@@ -154,13 +143,11 @@ fn test_solve_simple_semver_example() {
                     ("0.9.3",&[("postgres", "^0.10")])
                         ].iter().map(|&(v,r)| Spec::new(v,r)).collect());
 
+    smap
+}
 
-    let mut cfg = Config::new();
-    let ctx = Context::new(&cfg);
-    let opt = Optimize::new(&ctx);
-
+fn example_root() -> HashMap<String, VersionReq> {
     let mut root : HashMap<String, VersionReq> = HashMap::new();
-    let mut asts : HashMap<String, Ast> = HashMap::new();
 
     root.insert("postgres".to_string(),
                 VersionReq::parse("0.9").unwrap());
@@ -168,20 +155,29 @@ fn test_solve_simple_semver_example() {
     root.insert("r2d2-postgres".to_string(),
                 VersionReq::parse("0.9").unwrap());
 
+    root
+}
 
-    // Make a root Z3 Int constant for each pkg we're trying to solve for.
-    for (k, v) in &root {
+// Make a root Z3 Int constant for each pkg we're trying to solve for,
+// constrained to the range of versions the root asks for.
+fn assert_root_constraints<'ctx>(ctx: &'ctx Context, opt: &Optimize<'ctx>,
+                                 smap: &SpecMap,
+                                 root: &HashMap<String, VersionReq>)
+                                 -> HashMap<String, Ast<'ctx>> {
+    let mut asts : HashMap<String, Ast> = HashMap::new();
+
+    for (k, v) in root {
         let ast = ctx.fresh_int_const("root-pkg");
         info!("new AST for root {}", k);
 
-        match first_version_req_index(&smap, k, v) {
+        match first_version_req_index(smap, k, v) {
             None => (),
             Some(low) => {
                 info!("Asserting: {} >= #{} (root)", k, low);
                 opt.assert(&ast.ge(&ctx.from_u64(low as u64)))
             }
         }
-        match last_version_req_index(&smap, k, v) {
+        match last_version_req_index(smap, k, v) {
             None => (),
             Some(high) => {
                 info!("Asserting: {} <= #{} (root)", k, high);
@@ -191,11 +187,16 @@ fn test_solve_simple_semver_example() {
         asts.insert(k.clone(), ast);
     }
 
-    // Tell the optimizer to maximizes the sum of the root constants.
-    opt.maximize(&ctx.from_i64(0).add(&asts.values().collect::<Vec<&Ast>>()));
+    asts
+}
+
+fn assert_dependency_constraints<'ctx>(ctx: &'ctx Context,
+                                       opt: &Optimize<'ctx>,
+                                       smap: &SpecMap,
+                                       asts: &mut HashMap<String, Ast<'ctx>>) {
 
     // Ensure we have a constant for every pkg _or_ dep listed
-    for k in (&smap).keys() {
+    for k in smap.keys() {
         asts.entry(k.clone()).or_insert_with(|| {
             info!("new AST for {}", k);
             ctx.fresh_int_const("pkg")
@@ -203,7 +204,7 @@ fn test_solve_simple_semver_example() {
     }
     for specs in smap.values() {
         for spec in specs {
-            for r in (&spec).reqs.keys() {
+            for r in spec.reqs.keys() {
                 asts.entry(r.clone()).or_insert_with(|| {
                     info!("new AST for {}", r);
                     ctx.fresh_int_const("dep-pkg")
@@ -215,27 +216,27 @@ fn test_solve_simple_semver_example() {
     // Then assert all version constraints. Specifically: assert
     // an implication that whenever a package is of some version,
     // its required package is inside the acceptable range.
-    for (k, specs) in &smap {
+    for (k, specs) in smap {
         let k_ast = asts.get(k).unwrap();
-        for (n, spec) in (&specs).iter().enumerate() {
+        for (n, spec) in specs.iter().enumerate() {
             for (r, req) in &spec.reqs {
                 let r_ast = asts.get(r).unwrap();
-                match first_version_req_index(&smap, r, req) {
+                match first_version_req_index(smap, r, req) {
                     None => (),
                     Some(low) => {
                         info!("Asserting: {} == #{} {} => {} >= #{} {}",
-                              k, n, get_version(&smap, k, n as usize).unwrap(),
-                              r, low, get_version(&smap, r, low as usize).unwrap());
+                              k, n, get_version(smap, k, n).unwrap(),
+                              r, low, get_version(smap, r, low).unwrap());
                         opt.assert(&k_ast._eq(&ctx.from_u64(n as u64)).
                                    implies(&r_ast.ge(&ctx.from_u64(low as u64))))
                     }
                 }
-                match last_version_req_index(&smap, r, req) {
+                match last_version_req_index(smap, r, req) {
                     None => (),
                     Some(high) => {
                         info!("Asserting: {} == #{} {} => {} <= #{} {}",
-                              k, n, get_version(&smap, k, n as usize).unwrap(),
-                              r, high, get_version(&smap, r, high as usize).unwrap());
+                              k, n, get_version(smap, k, n).unwrap(),
+                              r, high, get_version(smap, r, high).unwrap());
                         opt.assert(&k_ast._eq(&ctx.from_u64(n as u64)).
                                    implies(&r_ast.le(&ctx.from_u64(high as u64))))
                     }
@@ -243,27 +244,105 @@ fn test_solve_simple_semver_example() {
             }
         }
     }
+}
+
+fn assert_solved_versions(smap: &SpecMap, asts: &HashMap<String, Ast>,
+                          model: &Model) {
+    let pg_a = asts.get("postgres").unwrap();
+    let r2_a = asts.get("r2d2-postgres").unwrap();
 
-    assert!(opt.check());
-    let model = opt.get_model();
+    let pg_v = model.eval(pg_a).unwrap().as_i64().unwrap() as usize;
+    let r2_v = model.eval(r2_a).unwrap().as_i64().unwrap() as usize;
+
+    assert!(get_version(smap, "postgres", pg_v).unwrap() ==
+            Version::parse("0.9.6").unwrap());
+
+    assert!(get_version(smap, "r2d2-postgres", r2_v).unwrap() ==
+            Version::parse("0.9.2").unwrap());
+}
+
+#[test]
+fn test_solve_simple_semver_example() {
+
+    // This is a little example of solving version constraints the way cargo
+    // might someday want to. It uses the optimizer portion of Z3.
+    // see: https://github.com/rust-lang/cargo/issues/2064
+
+    let _ = env_logger::init();
+
+    let smap = example_spec_map();
+    let root = example_root();
+
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let opt = Optimize::new(&ctx);
+
+    let mut asts = assert_root_constraints(&ctx, &opt, &smap, &root);
+
+    // Tell the optimizer to maximizes the sum of the root constants.
+    opt.maximize(&ctx.from_i64(0).add(&asts.values().collect::<Vec<&Ast>>()));
+
+    assert_dependency_constraints(&ctx, &opt, &smap, &mut asts);
+
+    assert_eq!(opt.check(), SatResult::Sat);
+    let model = opt.get_model().unwrap();
 
     for k in root.keys() {
         let ast = asts.get(k).unwrap();
-        let idx = model.eval(&ast).unwrap().as_i64().unwrap();
+        let idx = model.eval(ast).unwrap().as_i64().unwrap();
         info!("solved: {}: #{} = {}",
               k, idx, get_version(&smap, k, idx as usize).unwrap());
     }
 
-    let pg_a = asts.get("postgres").unwrap();
-    let r2_a = asts.get("r2d2-postgres").unwrap();
+    assert_solved_versions(&smap, &asts, &model);
+}
 
-    let pg_v = model.eval(&pg_a).unwrap().as_i64().unwrap() as usize;
-    let r2_v = model.eval(&r2_a).unwrap().as_i64().unwrap() as usize;
+#[test]
+fn test_solve_semver_example_with_soft_constraints() {
 
-    assert!(get_version(&smap, "postgres", pg_v).unwrap() ==
-            Version::parse("0.9.6").unwrap());
+    // The same problem, but preferring newer versions through soft
+    // constraints: one unit of penalty per version a root pkg falls
+    // short of the newest one its range allows.
 
-    assert!(get_version(&smap, "r2d2-postgres", r2_v).unwrap() ==
-            Version::parse("0.9.2").unwrap());
+    let _ = env_logger::init();
+
+    let smap = example_spec_map();
+    let root = example_root();
+
+    let cfg = Config::new();
+    let ctx = Context::new(&cfg);
+    let opt = Optimize::new(&ctx);
+
+    let mut asts = assert_root_constraints(&ctx, &opt, &smap, &root);
+
+    let mut softs = Vec::new();
+    for (k, v) in &root {
+        let ast = asts.get(k).unwrap();
+        let low = first_version_req_index(&smap, k, v).unwrap();
+        let high = last_version_req_index(&smap, k, v).unwrap();
+        for n in (low + 1)..(high + 1) {
+            info!("Preferring: {} >= #{}", k, n);
+            softs.push(opt.assert_soft(&ast.ge(&ctx.from_u64(n as u64)),
+                                       1, Some(k)));
+        }
+    }
+
+    assert_dependency_constraints(&ctx, &opt, &smap, &mut asts);
 
+    assert_eq!(opt.check(), SatResult::Sat);
+    let model = opt.get_model().unwrap();
+
+    assert_solved_versions(&smap, &asts, &model);
+
+    // r2d2-postgres 0.9.3 needs postgres ^0.10, so exactly one preference
+    // for it goes unsatisfied while postgres gets its newest version.
+    for soft in &softs {
+        let penalty = opt.get_penalty(soft).unwrap().as_i64().unwrap();
+        match soft.group() {
+            Some("postgres") => assert_eq!(penalty, 0),
+            Some("r2d2-postgres") => assert_eq!(penalty, 1),
+            _ => panic!("unexpected soft constraint group")
+        }
+    }
+    assert_eq!(softs.iter().filter(|s| !s.is_satisfied(&model)).count(), 1);
 }